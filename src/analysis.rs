@@ -0,0 +1,211 @@
+use crate::dsp::{DspFrame, DspProcessor};
+use crate::wav::read_wav;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+/// Spectral, beat, and loudness summary of one audio file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileSummary {
+    pub duration_secs: f32,
+    /// Mean of each `fft_result` bin over all frames (0-255)
+    pub avg_spectrum: [f32; 16],
+    /// Number of detected beats (rising edges of `sample_peak`)
+    pub beats: usize,
+    /// Tempo from the median interval between beats, if at least 3 were found
+    pub bpm: Option<f32>,
+    /// RMS level of the whole file in dBFS
+    pub loudness_dbfs: f32,
+}
+
+/// CSV header matching [`FileSummary::to_csv_row`], prefixed by a `file` column.
+pub fn csv_header() -> String {
+    let bins: Vec<String> = (0..16).map(|i| format!("bin{i}")).collect();
+    format!("file,duration_s,loudness_dbfs,beats,bpm,{}", bins.join(","))
+}
+
+impl FileSummary {
+    /// Formats the summary as one CSV row for `file`.
+    pub fn to_csv_row(&self, file: &str) -> String {
+        let bins: Vec<String> = self
+            .avg_spectrum
+            .iter()
+            .map(|b| format!("{b:.1}"))
+            .collect();
+        format!(
+            "{},{:.2},{:.1},{},{},{}",
+            csv_field(file),
+            self.duration_secs,
+            self.loudness_dbfs,
+            self.beats,
+            self.bpm.map(|b| format!("{b:.1}")).unwrap_or_default(),
+            bins.join(",")
+        )
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote, or newline.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Runs `samples` through a default `DspProcessor` as fast as possible
+/// (including the final partial window) and summarizes the frames.
+pub fn analyze_samples(samples: &[f32], sample_rate: u32) -> FileSummary {
+    let mut dsp = DspProcessor::new(sample_rate);
+    let mut frames = dsp.push_samples(samples);
+    frames.extend(dsp.finish());
+
+    let mut avg_spectrum = [0.0f32; 16];
+    for frame in &frames {
+        for (avg, &bin) in avg_spectrum.iter_mut().zip(&frame.fft_result) {
+            *avg += bin as f32;
+        }
+    }
+    if !frames.is_empty() {
+        avg_spectrum
+            .iter_mut()
+            .for_each(|b| *b /= frames.len() as f32);
+    }
+
+    let mean_square = if samples.is_empty() {
+        0.0
+    } else {
+        samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32
+    };
+
+    let onsets = beat_onsets(&frames);
+    let frame_secs = dsp.hop_size() as f32 / sample_rate as f32;
+    FileSummary {
+        duration_secs: samples.len() as f32 / sample_rate as f32,
+        avg_spectrum,
+        beats: onsets.len(),
+        bpm: estimate_bpm(&onsets, frame_secs),
+        // Floor at -120 dBFS so digital silence stays a finite number
+        loudness_dbfs: 10.0 * mean_square.max(1e-12).log10(),
+    }
+}
+
+/// Frame indices where `sample_peak` rises from 0 to 1.
+fn beat_onsets(frames: &[DspFrame]) -> Vec<usize> {
+    let mut prev = 0;
+    let mut onsets = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        if frame.sample_peak != 0 && prev == 0 {
+            onsets.push(i);
+        }
+        prev = frame.sample_peak;
+    }
+    onsets
+}
+
+/// Tempo from the median inter-onset interval, `None` with fewer than 3 beats.
+fn estimate_bpm(onsets: &[usize], frame_secs: f32) -> Option<f32> {
+    if onsets.len() < 3 {
+        return None;
+    }
+    let mut intervals: Vec<usize> = onsets.windows(2).map(|w| w[1] - w[0]).collect();
+    intervals.sort_unstable();
+    let median = intervals[intervals.len() / 2] as f32 * frame_secs;
+    Some(60.0 / median)
+}
+
+/// Analyzes every `.wav` file directly inside `dir`, sorted by name.
+///
+/// # Returns
+/// Each file with its summary, or the error reading it. `Err` only if the
+/// directory itself can't be read.
+pub fn analyze_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<(PathBuf, Result<FileSummary>)>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+        })
+        .collect();
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let summary = read_wav(&path).map(|a| analyze_samples(&a.samples, a.sample_rate));
+            (path, summary)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::WavRecorder;
+    use std::f32::consts::PI;
+
+    const SR: u32 = 48000;
+
+    /// Quiet 1 kHz bed with a 150 Hz kick every `60 / bpm` seconds.
+    fn kicks(bpm: f32, secs: f32) -> Vec<f32> {
+        let period = (SR as f32 * 60.0 / bpm) as usize;
+        (0..(SR as f32 * secs) as usize)
+            .map(|i| {
+                let t = i as f32 / SR as f32;
+                let k = (i % period) as f32 / SR as f32;
+                let kick = if k < 0.08 {
+                    0.8 * (-k * 40.0).exp() * (2.0 * PI * 150.0 * k).sin()
+                } else {
+                    0.0
+                };
+                0.05 * (2.0 * PI * 1000.0 * t).sin() + kick
+            })
+            .collect()
+    }
+
+    fn write(path: &Path, samples: &[f32]) {
+        let mut rec = WavRecorder::create(path, SR).unwrap();
+        rec.write_samples(samples).unwrap();
+        rec.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_analyze_dir_two_files() {
+        let dir = std::env::temp_dir().join(format!("wled-analyze-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write(&dir.join("a_kicks.wav"), &kicks(120.0, 6.0));
+        let tone: Vec<f32> = (0..SR as usize * 2)
+            .map(|i| 0.1 * (2.0 * PI * 440.0 * i as f32 / SR as f32).sin())
+            .collect();
+        write(&dir.join("b_tone.wav"), &tone);
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let results = analyze_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results.len(), 2);
+        let kicks = results[0].1.as_ref().unwrap();
+        let tone = results[1].1.as_ref().unwrap();
+
+        assert!((kicks.duration_secs - 6.0).abs() < 0.01);
+        let bpm = kicks.bpm.expect("kicks should yield a tempo");
+        assert!((bpm - 120.0).abs() < 10.0, "bpm {bpm}");
+
+        assert!((tone.duration_secs - 2.0).abs() < 0.01);
+        // 0.1 amplitude sine: RMS 0.0707 = -23 dBFS
+        assert!(
+            (tone.loudness_dbfs + 23.0).abs() < 0.5,
+            "{}",
+            tone.loudness_dbfs
+        );
+        assert!(tone.avg_spectrum.iter().any(|&b| b > 100.0));
+
+        let row = tone.to_csv_row("b_tone.wav");
+        assert_eq!(row.split(',').count(), csv_header().split(',').count());
+    }
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("plain.wav"), "plain.wav");
+        assert_eq!(csv_field("a,b \"c\".wav"), "\"a,b \"\"c\"\".wav\"");
+    }
+}
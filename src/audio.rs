@@ -1,11 +1,83 @@
+use crate::diagnostics::DeviceInfo;
+use crate::resample::Resampler;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{BuildStreamError, Device, FromSample, InputCallbackInfo, Sample, SampleFormat, Stream};
+use cpal::{
+    BufferSize, BuildStreamError, DefaultStreamConfigError, Device, FromSample, InputCallbackInfo,
+    PlayStreamError, Sample, SampleFormat, Stream, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
 use dialoguer::Select;
+use std::fmt;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub type CaptureStreamHandle = (Stream, u32, Receiver<Vec<f32>>, Arc<AtomicU64>);
+pub type CaptureStreamHandle = (
+    Stream,
+    u32,
+    Receiver<Vec<f32>>,
+    Arc<AtomicU64>,
+    Arc<AtomicU64>,
+);
+
+/// Why [`open_capture_stream`] could not open a capture stream.
+#[derive(Debug)]
+pub enum AudioError {
+    /// No device matched the hint, or no monitor device was found
+    DeviceNotFound,
+    /// The device has no default input config (often: it's output-only)
+    NoDefaultConfig(DefaultStreamConfigError),
+    /// The device only offers a sample format capture can't convert
+    UnsupportedFormat(SampleFormat),
+    /// `force_rate` isn't in any supported range (see [`force_input_config`])
+    UnsupportedRate(String),
+    /// A selected channel (0-based) doesn't exist on the device
+    ChannelOutOfRange {
+        channel: usize,
+        channels: usize,
+        device: String,
+    },
+    /// The driver refused to build the stream
+    BuildStream(BuildStreamError),
+    /// The stream was built but wouldn't start
+    StartStream(PlayStreamError),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::DeviceNotFound => write!(f, "Could not find audio device"),
+            AudioError::NoDefaultConfig(e) => write!(f, "No default input config: {e}"),
+            AudioError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported sample format: {format:?}")
+            }
+            AudioError::UnsupportedRate(message) => f.write_str(message),
+            AudioError::ChannelOutOfRange {
+                channel,
+                channels,
+                device,
+            } => write!(
+                f,
+                "Channel {} is out of range: '{device}' has {channels} channel(s)",
+                channel + 1
+            ),
+            AudioError::BuildStream(e) => write!(f, "Failed to build stream: {e}"),
+            AudioError::StartStream(e) => write!(f, "Failed to start stream: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AudioError::NoDefaultConfig(e) => Some(e),
+            AudioError::BuildStream(e) => Some(e),
+            AudioError::StartStream(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 /// Size of the bounded audio sample channel.
 ///
@@ -16,12 +88,560 @@ pub type CaptureStreamHandle = (Stream, u32, Receiver<Vec<f32>>, Arc<AtomicU64>)
 ///
 /// Value of 8 provides good balance between latency and dropout prevention.
 /// At 48kHz with typical chunk sizes, this represents ~10-20ms of buffering.
-const AUDIO_CHANNEL_SIZE: usize = 8;
+/// Overridden by [`CaptureOptions::queue_len`] (`--queue-len`).
+pub const AUDIO_CHANNEL_SIZE: usize = 8;
+
+/// Capture settings for [`open_capture_stream`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureOptions {
+    /// Source channels to keep (0-based, in this order) before swapping and
+    /// downmixing; empty keeps all of them (see [`select_channels`])
+    pub channels: Vec<usize>,
+    /// Swap the first two channels (see [`swap_left_right`])
+    pub swap_channels: bool,
+    /// How channels are folded to mono
+    pub downmix_mode: DownmixMode,
+    /// Open the device at exactly this rate instead of its default
+    pub force_rate: Option<u32>,
+    /// Resample the captured audio to this rate (see [`Resampler`]), so the
+    /// DSP sees the same rate whatever the device opened at
+    pub resample_to: Option<u32>,
+    /// Frames per driver callback, or `None` for the driver's default. A
+    /// size the driver rejects falls back to the default with a warning.
+    pub buffer_frames: Option<u32>,
+    /// Sample chunks queued between the callback and the DSP before new
+    /// ones are dropped. A longer queue rides out longer processing stalls
+    /// (GC or I/O spikes on a busy machine) without dropping audio, but a
+    /// backlog adds one callback buffer of latency per queued chunk.
+    pub queue_len: usize,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            swap_channels: false,
+            downmix_mode: DownmixMode::Average,
+            force_rate: None,
+            resample_to: None,
+            buffer_frames: None,
+            queue_len: AUDIO_CHANNEL_SIZE,
+        }
+    }
+}
+
+/// Sustained drop rate (chunks/sec) above which actionable advice is logged.
+///
+/// A few sporadic drops during I/O spikes are harmless; at this rate the
+/// consumer is persistently falling behind the capture callback.
+const DROP_STORM_RATE: f64 = 2.0;
+
+/// Turns raw dropped-chunk counts into one-time actionable advice.
+///
+/// Feed it the drops observed over each monitoring window; the first window
+/// whose rate exceeds the threshold yields a suggestion, later ones don't.
+pub struct DropAdvisor {
+    threshold_per_sec: f64,
+    advised: bool,
+}
+
+impl DropAdvisor {
+    pub fn new() -> Self {
+        Self::with_threshold(DROP_STORM_RATE)
+    }
+
+    /// Creates an advisor triggering above `threshold_per_sec` dropped chunks/sec.
+    pub fn with_threshold(threshold_per_sec: f64) -> Self {
+        Self {
+            threshold_per_sec,
+            advised: false,
+        }
+    }
+
+    /// Records `new_drops` over `window` and returns advice the first time
+    /// the drop rate exceeds the threshold.
+    pub fn observe(&mut self, new_drops: u64, window: Duration) -> Option<String> {
+        let secs = window.as_secs_f64();
+        if self.advised || secs <= 0.0 {
+            return None;
+        }
+        let rate = new_drops as f64 / secs;
+        if rate <= self.threshold_per_sec {
+            return None;
+        }
+        self.advised = true;
+        Some(format!(
+            "Audio is being dropped continuously ({rate:.1} chunks/sec; the capture queue holds \
+             {AUDIO_CHANNEL_SIZE} chunks by default). The DSP cannot keep up: close CPU-heavy applications, \
+             lower the device sample rate, or increase the capture queue size (--queue-len)."
+        ))
+    }
+}
+
+impl Default for DropAdvisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rebuilds the capture stream when drops stay high for too long.
+///
+/// Some driver glitches leave the stream running without an error callback
+/// while it drops most of its audio. Feed the policy the drops observed over
+/// each monitoring window; once the rate has stayed above the threshold for
+/// `sustain`, [`observe`](Self::observe) asks for a rebuild. A window at or
+/// below the threshold resets the count, so brief spikes never trigger it.
+pub struct RestartPolicy {
+    threshold_per_sec: f64,
+    sustain: Duration,
+    high_for: Duration, // Consecutive time spent above the threshold
+}
+
+impl RestartPolicy {
+    /// Creates a policy triggering after `sustain` above `threshold_per_sec`
+    /// dropped chunks/sec.
+    pub fn new(threshold_per_sec: f64, sustain: Duration) -> Self {
+        Self {
+            threshold_per_sec,
+            sustain,
+            high_for: Duration::ZERO,
+        }
+    }
+
+    /// Records `new_drops` over `window` and returns `true` when the stream
+    /// should be rebuilt. The policy starts over after triggering.
+    pub fn observe(&mut self, new_drops: u64, window: Duration) -> bool {
+        let secs = window.as_secs_f64();
+        if secs <= 0.0 || new_drops as f64 / secs <= self.threshold_per_sec {
+            self.high_for = Duration::ZERO;
+            return false;
+        }
+        self.high_for += window;
+        if self.high_for < self.sustain {
+            return false;
+        }
+        self.high_for = Duration::ZERO;
+        true
+    }
+}
+
+/// Wait before the first attempt to reopen a stalled capture device.
+pub const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between attempts to reopen a stalled capture device.
+pub const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Reopens the capture device when audio stops arriving.
+///
+/// Unplugging a USB interface or restarting PulseAudio kills the stream: the
+/// error callback fires once and the data callback never runs again, so the
+/// receiver stalls. Report every chunk with [`alive`](Self::alive); once none
+/// has arrived for `stall_after`, [`should_reconnect`](Self::should_reconnect)
+/// asks for a reopen, then again after [`RECONNECT_BACKOFF`], doubling up to
+/// [`RECONNECT_BACKOFF_MAX`] while the device stays away.
+pub struct ReconnectPolicy {
+    stall_after: Duration,
+    last_audio: Instant,
+    delay: Duration,               // Wait after the next attempt if it fails
+    next_attempt: Option<Instant>, // Earliest time for the next attempt
+}
+
+impl ReconnectPolicy {
+    /// Creates a policy that considers the stream dead after `stall_after`
+    /// without audio, counting from `now`.
+    pub fn new(stall_after: Duration, now: Instant) -> Self {
+        Self {
+            stall_after,
+            last_audio: now,
+            delay: RECONNECT_BACKOFF,
+            next_attempt: None,
+        }
+    }
+
+    /// Records audio arriving at `now`, which also resets the backoff.
+    pub fn alive(&mut self, now: Instant) {
+        self.last_audio = now;
+        self.delay = RECONNECT_BACKOFF;
+        self.next_attempt = None;
+    }
+
+    /// Returns the wait before the following attempt when a reopen is due at
+    /// `now`, or `None` while audio is flowing or the backoff hasn't elapsed.
+    pub fn should_reconnect(&mut self, now: Instant) -> Option<Duration> {
+        if now.saturating_duration_since(self.last_audio) < self.stall_after
+            || self.next_attempt.is_some_and(|at| now < at)
+        {
+            return None;
+        }
+        let wait = self.delay;
+        self.next_attempt = Some(now + wait);
+        self.delay = (wait * 2).min(RECONNECT_BACKOFF_MAX);
+        Some(wait)
+    }
+}
+
+/// Factor by which the interval between capture callbacks may exceed the
+/// duration of the previous buffer before it counts as a gap.
+///
+/// Callback timing jitters by a fraction of a buffer; a gap of a whole extra
+/// buffer or more means the driver lost audio before it reached us.
+const CAPTURE_GAP_TOLERANCE: f64 = 2.0;
+
+/// Detects gaps in the capture timeline from callback timestamps.
+///
+/// Each callback covers `frames / sample_rate` seconds of audio, so the next
+/// one should be captured about that much later. A much larger interval means
+/// the driver under-ran or dropped audio upstream of the bounded channel, a
+/// loss the channel drop counter never sees.
+pub struct GapDetector {
+    sample_rate: f64,
+    last: Option<(Duration, usize)>, // Capture time and frame count of the previous callback
+}
+
+impl GapDetector {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate as f64,
+            last: None,
+        }
+    }
+
+    /// Records a callback captured at `capture` (relative to any fixed
+    /// origin) holding `frames` frames.
+    ///
+    /// # Returns
+    /// The length of the missing audio if the interval since the previous
+    /// callback was abnormally large.
+    pub fn observe(&mut self, capture: Duration, frames: usize) -> Option<Duration> {
+        let gap = self.last.and_then(|(prev, prev_frames)| {
+            let expected = Duration::from_secs_f64(prev_frames as f64 / self.sample_rate);
+            let interval = capture.checked_sub(prev)?;
+            (interval.as_secs_f64() > expected.as_secs_f64() * CAPTURE_GAP_TOLERANCE)
+                .then(|| interval - expected)
+        });
+        self.last = Some((capture, frames));
+        gap
+    }
+}
+
+/// Number of attempts made to start a freshly built stream.
+///
+/// Some drivers briefly return a transient error from `play()` (e.g. right
+/// after the device resumes from sleep). With `PLAY_RETRY_BACKOFF` doubling
+/// between attempts, 4 attempts span roughly 700ms before giving up.
+const PLAY_ATTEMPTS: u32 = 4;
+
+/// Initial delay between `play()` attempts; doubled after each failure.
+const PLAY_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Time allowed for the capture stream to tear down on shutdown.
+///
+/// Some drivers block indefinitely when a stream is dropped; after this long
+/// the teardown is abandoned so the process can still exit promptly.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sample formats `build_stream` handles, most preferred first.
+///
+/// F32 needs no conversion and keeps full precision, so it wins whenever a
+/// device offers it, even if the driver's default is an integer format.
+/// Integer formats follow from widest to narrowest.
+const PREFERRED_FORMATS: &[SampleFormat] = &[
+    SampleFormat::F32,
+    SampleFormat::I32,
+    SampleFormat::I16,
+    SampleFormat::U16,
+    SampleFormat::I8,
+    SampleFormat::U8,
+];
+
+/// One step of the startup device fallback chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// First device whose name contains this text (case-insensitive)
+    Name(String),
+    /// First device with "monitor" in its name (PulseAudio/PipeWire loopback),
+    /// else the default output device where it can be captured in loopback
+    Monitor,
+    /// Interactive chooser over the usable input devices (and, on Windows,
+    /// the output devices to capture in loopback)
+    Interactive,
+    /// The host's default input device (usually the microphone)
+    Default,
+}
+
+impl std::str::FromStr for DeviceSelector {
+    type Err = String;
+
+    /// Parses `monitor`, `interactive`, `default`, or `name:<TEXT>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "monitor" => Ok(DeviceSelector::Monitor),
+            "interactive" => Ok(DeviceSelector::Interactive),
+            "default" => Ok(DeviceSelector::Default),
+            _ => match s.strip_prefix("name:") {
+                Some(name) if !name.is_empty() => Ok(DeviceSelector::Name(name.to_string())),
+                _ => Err(format!(
+                    "unknown device selector '{s}' (expected monitor, interactive, default, or name:<TEXT>)"
+                )),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelector::Name(name) => write!(f, "name:{name}"),
+            DeviceSelector::Monitor => f.write_str("monitor"),
+            DeviceSelector::Interactive => f.write_str("interactive"),
+            DeviceSelector::Default => f.write_str("default"),
+        }
+    }
+}
+
+/// Fallback chain used when none is configured: the interactive chooser,
+/// then monitor auto-detection if the chooser is cancelled.
+pub const DEFAULT_DEVICE_CHAIN: &[DeviceSelector] =
+    &[DeviceSelector::Interactive, DeviceSelector::Monitor];
+
+/// Resolves the first selector in `chain` that matches a device.
+///
+/// # Arguments
+/// * `devices` - Names of the capture devices: the input devices, then any
+///   output devices that can be captured in loopback
+/// * `default_device` - Name of the host's default input device, if any
+/// * `loopback_default` - Name of the default output device, if it can be
+///   captured in loopback; [`DeviceSelector::Monitor`] falls back to it
+/// * `interactive` - Runs the interactive chooser; `None` if cancelled
+///
+/// # Returns
+/// The matching selector and the chosen device name, or `None` if no
+/// selector resolves.
+pub fn resolve_device_chain<'a>(
+    chain: &'a [DeviceSelector],
+    devices: &[String],
+    default_device: Option<&str>,
+    loopback_default: Option<&str>,
+    mut interactive: impl FnMut() -> Option<String>,
+) -> Option<(&'a DeviceSelector, String)> {
+    chain.iter().find_map(|selector| {
+        let name = match selector {
+            DeviceSelector::Name(hint) => {
+                let hint = hint.to_lowercase();
+                devices
+                    .iter()
+                    .find(|n| n.to_lowercase().contains(&hint))
+                    .cloned()
+            }
+            DeviceSelector::Monitor => devices
+                .iter()
+                .find(|n| is_monitor_name(n))
+                .map(String::as_str)
+                .or(loopback_default)
+                .map(str::to_string),
+            DeviceSelector::Interactive => interactive(),
+            DeviceSelector::Default => default_device.map(str::to_string),
+        };
+        name.map(|name| (selector, name))
+    })
+}
+
+/// Picks the capture device by walking `chain` over the host's input
+/// devices (and, with loopback capture, its output devices), logging which
+/// selector matched.
+///
+/// Returns the device name to pass to [`open_capture_stream`], or `None` if
+/// no selector resolves.
+pub fn select_input_device(chain: &[DeviceSelector]) -> Option<String> {
+    let host = cpal::default_host();
+    #[allow(deprecated)]
+    let mut devices: Vec<String> = host
+        .input_devices()
+        .map(|devs| devs.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default();
+    #[allow(deprecated)]
+    devices.extend(loopback_devices(&host).iter().filter_map(|d| d.name().ok()));
+    #[allow(deprecated)]
+    let default_device = host.default_input_device().and_then(|d| d.name().ok());
+    #[allow(deprecated)]
+    let loopback_default = if LOOPBACK_CAPTURE {
+        host.default_output_device().and_then(|d| d.name().ok())
+    } else {
+        None
+    };
+
+    match resolve_device_chain(
+        chain,
+        &devices,
+        default_device.as_deref(),
+        loopback_default.as_deref(),
+        choose_input_device,
+    ) {
+        Some((selector, name)) => {
+            println!("Selected device '{name}' via {selector}");
+            Some(name)
+        }
+        None => {
+            let chain: Vec<String> = chain.iter().map(ToString::to_string).collect();
+            eprintln!(
+                "No device matched the selection chain [{}]",
+                chain.join(", ")
+            );
+            None
+        }
+    }
+}
+
+/// Whether a device name looks like a monitor source, which captures what an
+/// output device plays (PulseAudio/PipeWire name them "Monitor of ..." or
+/// "....monitor").
+pub fn is_monitor_name(name: &str) -> bool {
+    name.to_lowercase().contains("monitor")
+}
+
+/// One input device, as returned by [`enumerate_devices`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    /// Sample rate of the default input config, if it could be probed
+    pub default_sample_rate: Option<u32>,
+    /// Channel count of the default input config
+    pub channels: Option<u16>,
+    /// Sample formats capture can open, in `PREFERRED_FORMATS` order
+    pub formats: Vec<SampleFormat>,
+    /// Whether this is the host's default input device
+    pub is_default: bool,
+    /// Whether the name looks like a monitor source (see [`is_monitor_name`])
+    pub is_monitor: bool,
+}
+
+impl fmt::Display for AudioDeviceInfo {
+    /// One line for `--list-devices`, e.g.
+    /// `Monitor of Speakers: 2ch 48000Hz (F32, I16) [monitor]`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        match (self.channels, self.default_sample_rate) {
+            (Some(channels), Some(rate)) => write!(f, "{channels}ch {rate}Hz")?,
+            _ => f.write_str("no default config")?,
+        }
+        let formats: Vec<String> = self.formats.iter().map(|s| format!("{s:?}")).collect();
+        if !formats.is_empty() {
+            write!(f, " ({})", formats.join(", "))?;
+        }
+        if self.is_default {
+            f.write_str(" [default]")?;
+        }
+        if self.is_monitor {
+            f.write_str(" [monitor]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the host's input devices with their default config, the formats
+/// capture can open, and whether each looks like a monitor source, so
+/// callers can build their own device pickers.
+///
+/// Unlike the interactive chooser, nothing is filtered out.
+pub fn enumerate_devices() -> Vec<AudioDeviceInfo> {
+    let host = cpal::default_host();
+    #[allow(deprecated)]
+    let default_device = host.default_input_device().and_then(|d| d.name().ok());
+
+    with_stderr_suppressed(|| {
+        host.input_devices()
+            .map(|devs| {
+                devs.map(|d| {
+                    #[allow(deprecated)]
+                    let name = d.name().unwrap_or_else(|_| "<unknown>".into());
+                    let default = d.default_input_config().ok();
+                    let offered: Vec<SampleFormat> = d
+                        .supported_input_configs()
+                        .map(|ranges| ranges.map(|r| r.sample_format()).collect())
+                        .unwrap_or_default();
+                    AudioDeviceInfo {
+                        default_sample_rate: default.as_ref().map(|c| c.sample_rate()),
+                        channels: default.as_ref().map(|c| c.channels()),
+                        formats: PREFERRED_FORMATS
+                            .iter()
+                            .copied()
+                            .filter(|format| offered.contains(format))
+                            .collect(),
+                        is_default: default_device.as_ref() == Some(&name),
+                        is_monitor: is_monitor_name(&name),
+                        name,
+                    }
+                })
+                .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Lists every input device with its default config and the supported
+/// configs in formats `build_stream` can open, for `--diagnose`.
+///
+/// # Returns
+/// `(host name, default input device name, devices)`
+pub fn describe_input_devices() -> (String, Option<String>, Vec<DeviceInfo>) {
+    let host = cpal::default_host();
+    #[allow(deprecated)]
+    let default_device = host.default_input_device().and_then(|d| d.name().ok());
+
+    let devices = with_stderr_suppressed(|| {
+        host.input_devices()
+            .map(|devs| {
+                devs.map(|d| {
+                    #[allow(deprecated)]
+                    let name = d.name().unwrap_or_else(|_| "<unknown>".into());
+                    let default = d.default_input_config();
+                    let configs = d
+                        .supported_input_configs()
+                        .map(|ranges| {
+                            ranges
+                                .filter(|r| PREFERRED_FORMATS.contains(&r.sample_format()))
+                                .map(|r| {
+                                    format!(
+                                        "{}ch {}-{}Hz {:?}",
+                                        r.channels(),
+                                        r.min_sample_rate(),
+                                        r.max_sample_rate(),
+                                        r.sample_format()
+                                    )
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    DeviceInfo {
+                        name,
+                        sample_rate: default.as_ref().ok().map(|c| c.sample_rate()),
+                        default_config: default
+                            .map(|c| {
+                                format!(
+                                    "{}ch {}Hz {:?}",
+                                    c.channels(),
+                                    c.sample_rate(),
+                                    c.sample_format()
+                                )
+                            })
+                            .map_err(|e| e.to_string()),
+                        configs,
+                    }
+                })
+                .collect()
+            })
+            .unwrap_or_default()
+    });
+
+    (host.id().name().to_string(), default_device, devices)
+}
 
 /// Presents an interactive chooser over all cpal input devices.
 ///
 /// Works on all platforms. On macOS, users should have BlackHole (or similar)
-/// installed so that a loopback device appears in the list.
+/// installed so that a loopback device appears in the list. On Windows, the
+/// output devices are listed too, marked "(loopback)", since that's how
+/// system audio is captured there.
 ///
 /// Returns `Some(device_name)` on success, `None` if no devices are found or
 /// the user cancels.
@@ -31,7 +651,7 @@ pub fn choose_input_device() -> Option<String> {
 
     // Probe each device for a usable input config while suppressing ALSA/JACK
     // error spam that leaks to stderr when probing unsupported plugin devices.
-    let usable: Vec<String> = with_stderr_suppressed(|| {
+    let mut usable: Vec<String> = with_stderr_suppressed(|| {
         devices
             .into_iter()
             .filter_map(|d| {
@@ -57,10 +677,29 @@ pub fn choose_input_device() -> Option<String> {
             .collect()
     });
 
+    #[allow(deprecated)]
+    let loopback: Vec<String> = loopback_devices(&host)
+        .iter()
+        .filter_map(|d| d.name().ok())
+        .collect();
+    let inputs = usable.len();
+    usable.extend(loopback);
+
     if usable.is_empty() {
         eprintln!("No input devices found.");
         return None;
     }
+    let items: Vec<String> = usable
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            if i < inputs {
+                name.clone()
+            } else {
+                format!("{name} (loopback)")
+            }
+        })
+        .collect();
 
     // Default cursor to "default" if present, else "pulse", else first item.
     let default_idx = usable
@@ -71,7 +710,7 @@ pub fn choose_input_device() -> Option<String> {
 
     let selection = Select::new()
         .with_prompt("Select audio input device")
-        .items(&usable)
+        .items(&items)
         .default(default_idx)
         .interact()
         .ok()?;
@@ -114,17 +753,55 @@ fn with_stderr_suppressed<F: FnOnce() -> T, T>(f: F) -> T {
     f()
 }
 
-fn find_device(name_hint: Option<&str>) -> Option<Device> {
+/// Whether output devices can be captured in loopback mode.
+///
+/// WASAPI records what a render device plays when it's opened as an input,
+/// which is how Windows captures system audio: it has no monitor devices.
+/// Linux (PulseAudio/PipeWire monitors) and macOS (BlackHole) capture system
+/// audio through input devices instead.
+const LOOPBACK_CAPTURE: bool = cfg!(target_os = "windows");
+
+/// The host's output devices if they can be captured in loopback mode (see
+/// [`LOOPBACK_CAPTURE`]), else none.
+fn loopback_devices(host: &cpal::Host) -> Vec<Device> {
+    if !LOOPBACK_CAPTURE {
+        return Vec::new();
+    }
+    host.output_devices()
+        .map(|devices| devices.collect())
+        .unwrap_or_default()
+}
+
+/// Finds the capture device and whether it's an output device to capture in
+/// loopback mode (see [`LOOPBACK_CAPTURE`]).
+///
+/// A hint matches input devices first; with loopback capture, an output
+/// device of that name is the fallback. Without a hint, the first monitor
+/// device is used, else (with loopback capture) the default output device.
+fn find_device(name_hint: Option<&str>) -> Option<(Device, bool)> {
     let host = cpal::default_host();
     let devices: Vec<Device> = host.input_devices().ok()?.collect();
 
     if let Some(hint) = name_hint {
+        let candidates: Vec<(Device, bool)> = devices
+            .iter()
+            .map(|d| (d.clone(), false))
+            .chain(loopback_devices(&host).into_iter().map(|d| (d, true)))
+            .collect();
+        // An exact name (e.g. from the selection chain) wins over substrings
+        #[allow(deprecated)]
+        if let Some(found) = candidates
+            .iter()
+            .find(|(d, _)| d.name().is_ok_and(|n| n == hint))
+        {
+            return Some(found.clone());
+        }
         let hint_lower = hint.to_lowercase();
-        for dev in &devices {
+        for (dev, loopback) in candidates {
             #[allow(deprecated)]
             if let Ok(name) = dev.name() {
                 if name.to_lowercase().contains(&hint_lower) {
-                    return Some(dev.clone());
+                    return Some((dev, loopback));
                 }
             }
         }
@@ -136,12 +813,21 @@ fn find_device(name_hint: Option<&str>) -> Option<Device> {
     for dev in &devices {
         #[allow(deprecated)]
         if let Ok(name) = dev.name() {
-            if name.to_lowercase().contains("monitor") {
-                return Some(dev.clone());
+            if is_monitor_name(&name) {
+                return Some((dev.clone(), false));
             }
         }
     }
 
+    if LOOPBACK_CAPTURE {
+        if let Some(dev) = host.default_output_device() {
+            #[allow(deprecated)]
+            let name = dev.name().unwrap_or_else(|_| "<unknown>".into());
+            println!("No monitor device; capturing default output '{name}' in loopback mode");
+            return Some((dev, true));
+        }
+    }
+
     eprintln!("No monitor device found automatically.");
     None
 }
@@ -150,91 +836,440 @@ fn find_device(name_hint: Option<&str>) -> Option<Device> {
 ///
 /// # Arguments
 /// * `device_hint` - Optional device name substring for device selection.
-///   If `None`, auto-detects a monitor device.
+///   If `None`, auto-detects a monitor device; on Windows, falls back to
+///   the default output device in WASAPI loopback mode.
+/// * `options` - Channel handling, rate, and buffering (see [`CaptureOptions`])
 ///
 /// # Returns
-/// * `Ok((Stream, sample_rate, Receiver<Vec<f32>>, Arc<AtomicU64>))` - A tuple containing:
+/// * `Ok((Stream, sample_rate, Receiver<Vec<f32>>, Arc<AtomicU64>, Arc<AtomicU64>))` - A tuple containing:
 ///   - The active audio stream (must be kept alive)
-///   - Sample rate in Hz
+///   - Sample rate in Hz (`options.resample_to` when set)
 ///   - Channel receiver that yields mono f32 sample chunks
 ///   - Atomic counter for dropped sample chunks (for monitoring)
-/// * `Err(String)` - Error description if device cannot be opened
+///   - Atomic counter for capture gaps seen in callback timestamps (driver
+///     underruns, see [`GapDetector`])
+/// * `Err(AudioError)` - Why the device could not be opened
 ///
 /// # Notes
+/// - `options.channels` narrows multi-channel input to the selected
+///   channels; an index the device doesn't have is an error
 /// - Audio is automatically downmixed from stereo/multi-channel to mono
-/// - Uses a bounded channel (size 4) that drops samples if consumer is slow
-/// - Supports F32, I16, and U16 sample formats
+///   according to `options.downmix_mode`
+/// - With `options.resample_to`, the downmixed audio is converted to that
+///   rate, adding one device frame of latency (about 21µs at 48 kHz)
+/// - Uses a bounded channel (`options.queue_len` chunks) that drops samples
+///   if consumer is slow
+/// - Supports F32, I32, I16, U16, I8, and U8 sample formats
 /// - The Stream must remain in scope for capture to continue
 ///
 /// # Example
 /// ```no_run
-/// use wled_audio_server::audio::open_capture_stream;
+/// use wled_audio_server::audio::{open_capture_stream, AudioError, CaptureOptions};
 ///
-/// let (_stream, sample_rate, rx, _drop_counter) = open_capture_stream(Some("BlackHole 2ch"))?;
+/// let (_stream, sample_rate, rx, _drop_counter, _gap_counter) =
+///     open_capture_stream(Some("BlackHole 2ch"), &CaptureOptions::default())?;
 /// while let Ok(samples) = rx.recv() {
 ///     // Process samples...
 /// }
-/// # Ok::<(), String>(())
+/// # Ok::<(), AudioError>(())
 /// ```
-pub fn open_capture_stream(device_hint: Option<&str>) -> Result<CaptureStreamHandle, String> {
-    let device = find_device(device_hint).ok_or("Could not find audio device")?;
+pub fn open_capture_stream(
+    device_hint: Option<&str>,
+    options: &CaptureOptions,
+) -> Result<CaptureStreamHandle, AudioError> {
+    let (device, loopback) = find_device(device_hint).ok_or(AudioError::DeviceNotFound)?;
     #[allow(deprecated)]
     let dev_name = device.name().unwrap_or_else(|_| "<unknown>".into());
 
-    let config = device
-        .default_input_config()
-        .map_err(|e| format!("No default input config: {e}"))?;
+    // A loopback capture records in one of the output device's own formats
+    let default_config = if loopback {
+        device.default_output_config()
+    } else {
+        device.default_input_config()
+    }
+    .map_err(AudioError::NoDefaultConfig)?;
+
+    let supported: Vec<_> = if loopback {
+        device
+            .supported_output_configs()
+            .map(|ranges| ranges.collect())
+    } else {
+        device
+            .supported_input_configs()
+            .map(|ranges| ranges.collect())
+    }
+    .unwrap_or_default();
+    let config = match options.force_rate {
+        Some(rate) => force_input_config(&supported, rate).map_err(AudioError::UnsupportedRate)?,
+        // Prefer the best-supported sample format at the default rate rather
+        // than blindly trusting the driver's default format
+        None => {
+            choose_input_config(&supported, default_config.sample_rate()).unwrap_or(default_config)
+        }
+    };
 
-    let sample_rate = config.sample_rate();
+    let device_rate = config.sample_rate();
     let channels = config.channels() as usize;
+    if !PREFERRED_FORMATS.contains(&config.sample_format()) {
+        return Err(AudioError::UnsupportedFormat(config.sample_format()));
+    }
+
+    if let Some(&bad) = options.channels.iter().find(|&&c| c >= channels) {
+        return Err(AudioError::ChannelOutOfRange {
+            channel: bad,
+            channels,
+            device: dev_name,
+        });
+    }
 
-    println!("Using device: {dev_name}");
-    println!("Sample rate: {sample_rate} Hz, channels: {channels}");
+    println!(
+        "Using device: {dev_name}{}",
+        if loopback { " (loopback)" } else { "" }
+    );
+    println!("Sample rate: {device_rate} Hz, channels: {channels}");
+    if !options.channels.is_empty() {
+        let picked: Vec<String> = options
+            .channels
+            .iter()
+            .map(|c| (c + 1).to_string())
+            .collect();
+        println!("Capturing channel(s): {}", picked.join(", "));
+    }
+
+    let sample_rate = match options.resample_to {
+        Some(rate) if rate != device_rate => {
+            println!(
+                "Resampling {device_rate} Hz -> {rate} Hz (adds {:.0}µs latency)",
+                1e6 / f64::from(device_rate)
+            );
+            rate
+        }
+        _ => device_rate,
+    };
 
-    let (tx, rx): (SyncSender<Vec<f32>>, Receiver<Vec<f32>>) = sync_channel(AUDIO_CHANNEL_SIZE);
+    let (tx, rx): (SyncSender<Vec<f32>>, Receiver<Vec<f32>>) =
+        sync_channel(options.queue_len.max(1));
     let drop_counter = Arc::new(AtomicU64::new(0));
+    let gap_counter = Arc::new(AtomicU64::new(0));
 
-    let stream = match config.sample_format() {
-        SampleFormat::F32 => {
-            build_stream::<f32>(&device, &config.into(), channels, tx, drop_counter.clone())
+    let build = |stream_config: &cpal::StreamConfig| {
+        let layout = (channels, options);
+        let resampler = (sample_rate != device_rate).then(|| {
+            let out_channels = match options.downmix_mode {
+                DownmixMode::Stereo => 2,
+                _ => 1,
+            };
+            Resampler::new(device_rate, sample_rate, out_channels)
+        });
+        let counters = (drop_counter.clone(), gap_counter.clone());
+        let tx = tx.clone();
+        match config.sample_format() {
+            SampleFormat::F32 => {
+                build_stream::<f32>(&device, stream_config, layout, resampler, tx, counters)
+            }
+            SampleFormat::I16 => {
+                build_stream::<i16>(&device, stream_config, layout, resampler, tx, counters)
+            }
+            SampleFormat::U16 => {
+                build_stream::<u16>(&device, stream_config, layout, resampler, tx, counters)
+            }
+            SampleFormat::I32 => {
+                build_stream::<i32>(&device, stream_config, layout, resampler, tx, counters)
+            }
+            SampleFormat::I8 => {
+                build_stream::<i8>(&device, stream_config, layout, resampler, tx, counters)
+            }
+            SampleFormat::U8 => {
+                build_stream::<u8>(&device, stream_config, layout, resampler, tx, counters)
+            }
+            // Rejected above
+            _ => Err(BuildStreamError::StreamConfigNotSupported),
+        }
+    };
+    let mut stream_config: cpal::StreamConfig = config.config();
+    let stream = match options.buffer_frames {
+        Some(frames) => {
+            stream_config.buffer_size = BufferSize::Fixed(frames);
+            build(&stream_config).or_else(|e| {
+                eprintln!(
+                    "Warning: device rejected a {frames}-frame buffer ({e}); using its default"
+                );
+                stream_config.buffer_size = BufferSize::Default;
+                build(&stream_config)
+            })
         }
-        SampleFormat::I16 => {
-            build_stream::<i16>(&device, &config.into(), channels, tx, drop_counter.clone())
+        None => build(&stream_config),
+    }
+    .map_err(AudioError::BuildStream)?;
+
+    retry_with_backoff(PLAY_ATTEMPTS, PLAY_RETRY_BACKOFF, || stream.play())
+        .map_err(AudioError::StartStream)?;
+
+    Ok((stream, sample_rate, rx, drop_counter, gap_counter))
+}
+
+/// Picks the input config to open from a device's supported ranges.
+///
+/// Formats are tried in `PREFERRED_FORMATS` order (F32, then I16, then U16).
+/// Within a format, a range containing `preferred_rate` wins; otherwise the
+/// rate is clamped into the first range of that format.
+///
+/// # Returns
+/// `None` if no range uses a format `build_stream` supports.
+pub fn choose_input_config(
+    supported: &[SupportedStreamConfigRange],
+    preferred_rate: u32,
+) -> Option<SupportedStreamConfig> {
+    PREFERRED_FORMATS.iter().find_map(|&format| {
+        let mut candidates = supported.iter().filter(|r| r.sample_format() == format);
+        let in_range = candidates
+            .clone()
+            .find(|r| (r.min_sample_rate()..=r.max_sample_rate()).contains(&preferred_rate));
+        match in_range {
+            Some(range) => Some(range.with_sample_rate(preferred_rate)),
+            None => candidates.next().map(|range| {
+                let rate = preferred_rate.clamp(range.min_sample_rate(), range.max_sample_rate());
+                range.with_sample_rate(rate)
+            }),
         }
-        SampleFormat::U16 => {
-            build_stream::<u16>(&device, &config.into(), channels, tx, drop_counter.clone())
+    })
+}
+
+/// Picks an input config that runs the device at exactly `rate` Hz, for
+/// `--force-rate`.
+///
+/// Unlike [`choose_input_config`], the rate is never clamped: only ranges
+/// containing `rate` qualify, tried in `PREFERRED_FORMATS` order.
+///
+/// # Returns
+/// An error listing the supported rates if no usable range contains `rate`.
+pub fn force_input_config(
+    supported: &[SupportedStreamConfigRange],
+    rate: u32,
+) -> Result<SupportedStreamConfig, String> {
+    PREFERRED_FORMATS
+        .iter()
+        .find_map(|&format| {
+            supported.iter().find(|r| {
+                r.sample_format() == format
+                    && (r.min_sample_rate()..=r.max_sample_rate()).contains(&rate)
+            })
+        })
+        .map(|range| range.with_sample_rate(rate))
+        .ok_or_else(|| {
+            let ranges: Vec<String> = supported
+                .iter()
+                .filter(|r| PREFERRED_FORMATS.contains(&r.sample_format()))
+                .map(|r| {
+                    format!(
+                        "{}-{} Hz ({:?})",
+                        r.min_sample_rate(),
+                        r.max_sample_rate(),
+                        r.sample_format()
+                    )
+                })
+                .collect();
+            format!(
+                "Device does not support {rate} Hz (supported: {})",
+                if ranges.is_empty() {
+                    "none".to_string()
+                } else {
+                    ranges.join(", ")
+                }
+            )
+        })
+}
+
+/// Runs `f` on a helper thread and waits at most `timeout` for it to finish.
+///
+/// Used to drop the capture stream on shutdown without risking a hang.
+///
+/// # Returns
+/// `Some(result)` if `f` completed in time, `None` if the deadline passed.
+/// On timeout the helper thread is left detached and dies with the process.
+pub fn run_bounded<T: Send + 'static>(
+    timeout: Duration,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.recv_timeout(timeout).ok()
+}
+
+/// Runs `op` up to `attempts` times, sleeping with exponential backoff
+/// between failures and logging each failed attempt.
+///
+/// Returns the first success, or the last error once all attempts fail.
+fn retry_with_backoff<T, E: std::fmt::Display>(
+    attempts: u32,
+    initial_backoff: Duration,
+    mut op: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut backoff = initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < attempts => {
+                eprintln!("Attempt {attempt}/{attempts} to start stream failed: {e}; retrying");
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
         }
-        fmt => return Err(format!("Unsupported sample format: {fmt:?}")),
     }
-    .map_err(|e| format!("Failed to build stream: {e}"))?;
+}
+
+/// Swaps the first two channels of every interleaved frame in place, for
+/// interfaces wired with left and right reversed. Mono input is unchanged.
+pub fn swap_left_right(samples: &mut [f32], channels: usize) {
+    if channels < 2 {
+        return;
+    }
+    for frame in samples.chunks_exact_mut(channels) {
+        frame.swap(0, 1);
+    }
+}
+
+/// Keeps only the `selection` channels (0-based, in that order) of each
+/// interleaved frame of `samples`.
+///
+/// # Returns
+/// `None` if `samples` is not a whole number of `channels`-wide frames or a
+/// selected index is out of range.
+pub fn select_channels(samples: &[f32], channels: usize, selection: &[usize]) -> Option<Vec<f32>> {
+    if channels == 0
+        || !samples.len().is_multiple_of(channels)
+        || selection.iter().any(|&c| c >= channels)
+    {
+        return None;
+    }
+    Some(
+        samples
+            .chunks_exact(channels)
+            .flat_map(|frame| selection.iter().map(|&c| frame[c]))
+            .collect(),
+    )
+}
+
+/// How [`downmix`] folds each multi-channel frame into one mono sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DownmixMode {
+    /// Mean of all channels; out-of-phase content cancels
+    #[default]
+    Average,
+    /// The channel with the largest magnitude, keeping its sign, so
+    /// transients survive even when other channels are out of phase
+    Max,
+    /// No fold: the first two channels are kept as interleaved left/right
+    /// pairs for separate analysis (mono input is duplicated to both)
+    Stereo,
+}
 
-    stream
-        .play()
-        .map_err(|e| format!("Failed to start stream: {e}"))?;
+/// Folds each interleaved frame of `samples` down to one mono sample, or to
+/// one left/right pair in [`DownmixMode::Stereo`].
+///
+/// # Returns
+/// `None` if `samples` is not a whole number of `channels`-wide frames,
+/// e.g. after the device renegotiated its channel count mid-stream;
+/// downmixing such a buffer would mix samples from different channels.
+pub fn downmix(samples: &[f32], channels: usize, mode: DownmixMode) -> Option<Vec<f32>> {
+    if channels == 0 || !samples.len().is_multiple_of(channels) {
+        return None;
+    }
+    let frames = samples.chunks_exact(channels);
+    Some(match mode {
+        DownmixMode::Average => frames
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect(),
+        DownmixMode::Max => frames
+            .map(|frame| {
+                frame
+                    .iter()
+                    .copied()
+                    .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                    .unwrap_or(0.0)
+            })
+            .collect(),
+        DownmixMode::Stereo => frames
+            .flat_map(|frame| [frame[0], frame[1.min(channels - 1)]])
+            .collect(),
+    })
+}
 
-    Ok((stream, sample_rate, rx, drop_counter))
+/// Converts device samples to f32 in -1.0..=1.0 (unsigned formats are
+/// centered on their midpoint).
+fn to_f32<T: cpal::SizedSample>(data: &[T]) -> Vec<f32>
+where
+    f32: FromSample<T>,
+{
+    data.iter().map(|s| f32::from_sample(*s)).collect()
 }
 
 fn build_stream<T: cpal::SizedSample + Send + 'static>(
     device: &Device,
     config: &cpal::StreamConfig,
-    channels: usize,
+    (channels, options): (usize, &CaptureOptions),
+    mut resampler: Option<Resampler>,
     tx: SyncSender<Vec<f32>>,
-    drop_counter: Arc<AtomicU64>,
+    (drop_counter, gap_counter): (Arc<AtomicU64>, Arc<AtomicU64>),
 ) -> Result<Stream, BuildStreamError>
 where
     f32: FromSample<T>,
 {
+    let selection = options.channels.clone();
+    let (swap_channels, downmix_mode) = (options.swap_channels, options.downmix_mode);
+    // Channels per frame once the selection is applied
+    let kept = if selection.is_empty() {
+        channels
+    } else {
+        selection.len()
+    };
+    let mut gaps = GapDetector::new(config.sample_rate);
+    let mut origin = None;
+    let mut warned_layout = false;
     device.build_input_stream(
         config,
-        move |data: &[T], _: &InputCallbackInfo| {
-            let mono: Vec<f32> = data
-                .chunks(channels)
-                .map(|frame| {
-                    let sum: f32 = frame.iter().map(|s| f32::from_sample(*s)).sum();
-                    sum / channels as f32
-                })
-                .collect();
+        move |data: &[T], info: &InputCallbackInfo| {
+            let capture = info.timestamp().capture;
+            let origin = *origin.get_or_insert(capture);
+            if let Some(offset) = capture.duration_since(&origin) {
+                if gaps.observe(offset, data.len() / channels).is_some() {
+                    gap_counter.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            let samples = to_f32(data);
+            let selected = if selection.is_empty() {
+                Some(samples)
+            } else {
+                select_channels(&samples, channels, &selection)
+            };
+            let mono = selected.and_then(|mut samples| {
+                if swap_channels {
+                    swap_left_right(&mut samples, kept);
+                }
+                let mixed = downmix(&samples, kept, downmix_mode)?;
+                match resampler.as_mut() {
+                    Some(resampler) => resampler.process(&mixed),
+                    None => Some(mixed),
+                }
+            });
+            let Some(mono) = mono else {
+                if !warned_layout {
+                    eprintln!(
+                        "Warning: got a {}-sample buffer, not a multiple of {channels} channels; \
+                         skipping malformed buffers (did the device change its channel layout?)",
+                        data.len()
+                    );
+                    warned_layout = true;
+                }
+                drop_counter.fetch_add(1, Ordering::Relaxed);
+                return;
+            };
             // Drop samples if the consumer can't keep up (bounded channel)
             if tx.try_send(mono).is_err() {
                 drop_counter.fetch_add(1, Ordering::Relaxed);
@@ -246,3 +1281,483 @@ where
         None,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(format: SampleFormat, min: u32, max: u32) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(2, min, max, cpal::SupportedBufferSize::Unknown, format)
+    }
+
+    #[test]
+    fn test_swap_left_right_moves_right_to_left() {
+        // Signal only on the physical right channel
+        let mut samples = vec![0.0, 0.5, 0.0, -0.25, 0.0, 1.0];
+        swap_left_right(&mut samples, 2);
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let right: Vec<f32> = samples.iter().skip(1).step_by(2).copied().collect();
+        assert_eq!(left, [0.5, -0.25, 1.0]);
+        assert_eq!(right, [0.0, 0.0, 0.0]);
+        // Mono downmix is unaffected by the swap
+        assert_eq!(
+            downmix(&samples, 2, DownmixMode::Average).unwrap(),
+            [0.25, -0.125, 0.5]
+        );
+    }
+
+    #[test]
+    fn test_downmix_rejects_channel_mismatch() {
+        // Device switched from stereo to 5.1 mid-buffer: a trailing partial
+        // frame would otherwise be averaged as if it were stereo
+        let surround = vec![0.5; 6 * 2 + 1];
+        let avg = DownmixMode::Average;
+        assert_eq!(downmix(&surround, 2, avg), None);
+        assert_eq!(downmix(&[0.1, 0.2, 0.3], 2, avg), None);
+        assert_eq!(downmix(&[0.1, 0.2], 0, avg), None);
+        assert_eq!(
+            downmix(&[0.25, 0.75, 0.5, 1.0], 2, avg),
+            Some(vec![0.5, 0.75])
+        );
+        assert_eq!(downmix(&[0.1, 0.2, 0.3], 2, DownmixMode::Max), None);
+    }
+
+    #[test]
+    fn test_max_downmix_keeps_out_of_phase_amplitude() {
+        // Left and right carry the same signal with opposite polarity
+        // (the right channel a little quieter)
+        let samples = [0.5, -0.25, -0.75, 0.5, 0.25, -0.25];
+        assert_eq!(
+            downmix(&samples, 2, DownmixMode::Average).unwrap(),
+            [0.125, -0.125, 0.0]
+        );
+        // Max keeps the dominant channel's value, sign included
+        let max = downmix(&samples, 2, DownmixMode::Max).unwrap();
+        assert_eq!(&max[..2], [0.5, -0.75]);
+        assert_eq!(max[2].abs(), 0.25);
+    }
+
+    #[test]
+    fn test_stereo_downmix_keeps_left_and_right() {
+        let surround = [0.1, 0.2, 0.9, 0.3, 0.4, 0.9];
+        assert_eq!(
+            downmix(&surround, 3, DownmixMode::Stereo),
+            Some(vec![0.1, 0.2, 0.3, 0.4])
+        );
+        assert_eq!(
+            downmix(&[0.5, -0.5], 1, DownmixMode::Stereo),
+            Some(vec![0.5, 0.5, -0.5, -0.5])
+        );
+    }
+
+    #[test]
+    fn test_select_channels_picks_source_channels() {
+        // Four channels; the turntable is on the third
+        let samples = [0.0, 0.0, 0.5, 0.0, 0.0, 0.0, -0.25, 0.0];
+        assert_eq!(select_channels(&samples, 4, &[2]), Some(vec![0.5, -0.25]));
+        assert_eq!(
+            select_channels(&samples, 4, &[2, 0]),
+            Some(vec![0.5, 0.0, -0.25, 0.0])
+        );
+        assert_eq!(select_channels(&samples, 4, &[4]), None);
+        assert_eq!(select_channels(&samples[..7], 4, &[2]), None);
+
+        // Picking a channel avoids the silent ones diluting the downmix
+        let picked = select_channels(&samples, 4, &[2]).unwrap();
+        assert_eq!(
+            downmix(&picked, 1, DownmixMode::Average),
+            Some(vec![0.5, -0.25])
+        );
+        assert_eq!(
+            downmix(&samples, 4, DownmixMode::Average),
+            Some(vec![0.125, -0.0625])
+        );
+    }
+
+    #[test]
+    fn test_swap_left_right_mono_and_surround() {
+        let mut mono = vec![0.1, 0.2, 0.3];
+        swap_left_right(&mut mono, 1);
+        assert_eq!(mono, [0.1, 0.2, 0.3]);
+
+        // Only front left/right are swapped; other channels stay in place
+        let mut surround = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        swap_left_right(&mut surround, 3);
+        assert_eq!(surround, [2.0, 1.0, 3.0, 5.0, 4.0, 6.0]);
+    }
+
+    #[test]
+    fn test_force_input_config_uses_requested_rate() {
+        let supported = [
+            range(SampleFormat::I16, 44100, 44100),
+            range(SampleFormat::F32, 44100, 44100),
+            range(SampleFormat::I16, 48000, 96000),
+        ];
+        // Only the I16 range covers 48 kHz, so it beats the preferred F32
+        let config = force_input_config(&supported, 48000).unwrap();
+        assert_eq!(config.sample_rate(), 48000);
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+
+        let config = force_input_config(&supported, 44100).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn test_force_input_config_rejects_unsupported_rate() {
+        let supported = [
+            range(SampleFormat::F32, 44100, 44100),
+            range(SampleFormat::I64, 8000, 192000),
+        ];
+        let err = force_input_config(&supported, 48000).unwrap_err();
+        assert!(err.contains("48000 Hz"), "{err}");
+        assert!(err.contains("44100-44100 Hz (F32)"), "{err}");
+        assert!(!err.contains("I64"), "{err}");
+    }
+
+    #[test]
+    fn test_choose_input_config_prefers_f32() {
+        let supported = [
+            range(SampleFormat::I16, 44100, 48000),
+            range(SampleFormat::F32, 44100, 48000),
+            range(SampleFormat::U16, 44100, 48000),
+        ];
+        let config = choose_input_config(&supported, 48000).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::F32);
+        assert_eq!(config.sample_rate(), 48000);
+    }
+
+    #[test]
+    fn test_integer_formats_convert_to_unit_range() {
+        assert_eq!(to_f32(&[i32::MIN, 0]), vec![-1.0, 0.0]);
+        assert!((to_f32(&[i32::MAX])[0] - 1.0).abs() < 1e-6);
+        assert_eq!(to_f32(&[i8::MIN, 0, 64]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(to_f32(&[0u8, 128, 192]), vec![-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_choose_input_config_prefers_wide_integers() {
+        let supported = [
+            range(SampleFormat::U8, 8000, 96000),
+            range(SampleFormat::I16, 8000, 96000),
+            range(SampleFormat::I32, 8000, 96000),
+        ];
+        let config = choose_input_config(&supported, 48000).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I32);
+
+        let narrow = [
+            range(SampleFormat::U8, 8000, 96000),
+            range(SampleFormat::I8, 8000, 96000),
+        ];
+        let config = choose_input_config(&narrow, 48000).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I8);
+    }
+
+    #[test]
+    fn test_choose_input_config_falls_back_by_preference() {
+        let supported = [
+            range(SampleFormat::U16, 8000, 96000),
+            range(SampleFormat::I16, 8000, 96000),
+            range(SampleFormat::I64, 8000, 96000),
+        ];
+        let config = choose_input_config(&supported, 44100).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+
+        let unsupported = [range(SampleFormat::I64, 8000, 96000)];
+        assert!(choose_input_config(&unsupported, 44100).is_none());
+    }
+
+    #[test]
+    fn test_choose_input_config_prefers_range_with_rate() {
+        let supported = [
+            range(SampleFormat::F32, 8000, 22050),
+            range(SampleFormat::F32, 44100, 48000),
+        ];
+        assert_eq!(
+            choose_input_config(&supported, 48000)
+                .unwrap()
+                .sample_rate(),
+            48000
+        );
+        // No range contains 96000: clamp into the first F32 range
+        assert_eq!(
+            choose_input_config(&supported, 96000)
+                .unwrap()
+                .sample_rate(),
+            22050
+        );
+    }
+
+    #[test]
+    fn test_drop_advisor_fires_once_on_sustained_drops() {
+        let mut advisor = DropAdvisor::with_threshold(2.0);
+        let window = Duration::from_secs(5);
+
+        let advice = advisor
+            .observe(50, window)
+            .expect("10 drops/sec should trigger");
+        assert!(advice.contains(&AUDIO_CHANNEL_SIZE.to_string()));
+        assert!(advisor.observe(50, window).is_none(), "advice is one-time");
+    }
+
+    #[test]
+    fn test_drop_advisor_ignores_sporadic_drops() {
+        let mut advisor = DropAdvisor::with_threshold(2.0);
+        let window = Duration::from_secs(5);
+        for drops in [0, 3, 1, 10] {
+            assert!(advisor.observe(drops, window).is_none());
+        }
+    }
+
+    #[test]
+    fn test_restart_policy_triggers_on_sustained_drops() {
+        let mut policy = RestartPolicy::new(2.0, Duration::from_secs(15));
+        let window = Duration::from_secs(5);
+
+        assert!(!policy.observe(50, window));
+        assert!(!policy.observe(50, window));
+        assert!(policy.observe(50, window), "15s at 10 drops/sec");
+        // Starts over after a rebuild
+        assert!(!policy.observe(50, window));
+    }
+
+    #[test]
+    fn test_restart_policy_ignores_brief_spikes() {
+        let mut policy = RestartPolicy::new(2.0, Duration::from_secs(15));
+        let window = Duration::from_secs(5);
+
+        // High windows interrupted by quiet ones never add up to 15s
+        for drops in [500, 500, 0, 500, 500, 10, 500, 0, 0] {
+            assert!(!policy.observe(drops, window), "{drops}");
+        }
+        assert!(!policy.observe(50, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_audio_device_info_formats_one_line() {
+        assert!(is_monitor_name("Monitor of Built-in Audio Analog Stereo"));
+        assert!(is_monitor_name(
+            "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor"
+        ));
+        assert!(!is_monitor_name("BlackHole 2ch"));
+
+        let mut info = AudioDeviceInfo {
+            name: "Monitor of Speakers".into(),
+            default_sample_rate: Some(48000),
+            channels: Some(2),
+            formats: vec![SampleFormat::F32, SampleFormat::I16],
+            is_default: false,
+            is_monitor: true,
+        };
+        assert_eq!(
+            info.to_string(),
+            "Monitor of Speakers: 2ch 48000Hz (F32, I16) [monitor]"
+        );
+        info.default_sample_rate = None;
+        info.formats.clear();
+        info.is_default = true;
+        assert_eq!(
+            info.to_string(),
+            "Monitor of Speakers: no default config [default] [monitor]"
+        );
+    }
+
+    #[test]
+    fn test_audio_error_formats_like_before() {
+        use std::error::Error;
+
+        let err = AudioError::ChannelOutOfRange {
+            channel: 4,
+            channels: 2,
+            device: "USB Audio".into(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Channel 5 is out of range: 'USB Audio' has 2 channel(s)"
+        );
+        assert!(err.source().is_none());
+        assert_eq!(
+            AudioError::UnsupportedFormat(SampleFormat::F64).to_string(),
+            "Unsupported sample format: F64"
+        );
+
+        let err = AudioError::BuildStream(BuildStreamError::DeviceNotAvailable);
+        assert!(err.to_string().starts_with("Failed to build stream: "));
+        assert!(err.source().is_some_and(|e| e.is::<BuildStreamError>()));
+    }
+
+    #[test]
+    fn test_reconnect_policy_backs_off_while_stalled() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut policy = ReconnectPolicy::new(Duration::from_secs(3), start);
+
+        assert_eq!(policy.should_reconnect(at(2)), None, "not stalled yet");
+        assert_eq!(policy.should_reconnect(at(3)), Some(Duration::from_secs(1)));
+        assert_eq!(policy.should_reconnect(at(3)), None, "backing off");
+        assert_eq!(policy.should_reconnect(at(4)), Some(Duration::from_secs(2)));
+        assert_eq!(policy.should_reconnect(at(5)), None);
+        assert_eq!(policy.should_reconnect(at(6)), Some(Duration::from_secs(4)));
+
+        // The wait stops growing at the cap
+        let mut now = 6;
+        let mut wait = Duration::ZERO;
+        for _ in 0..10 {
+            now += 60;
+            wait = policy.should_reconnect(at(now)).unwrap();
+        }
+        assert_eq!(wait, RECONNECT_BACKOFF_MAX);
+
+        // Audio arriving again starts over
+        policy.alive(at(now));
+        assert_eq!(policy.should_reconnect(at(now + 2)), None);
+        assert_eq!(
+            policy.should_reconnect(at(now + 3)),
+            Some(RECONNECT_BACKOFF)
+        );
+    }
+
+    #[test]
+    fn test_run_bounded_returns_result() {
+        assert_eq!(run_bounded(Duration::from_secs(1), || 42), Some(42));
+    }
+
+    #[test]
+    fn test_run_bounded_returns_by_deadline_when_blocked() {
+        let start = std::time::Instant::now();
+        let result = run_bounded(Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_secs(10));
+        });
+        assert!(result.is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_retry_succeeds_after_transient_failures() {
+        let mut calls = 0;
+        let result = retry_with_backoff(4, Duration::ZERO, || {
+            calls += 1;
+            if calls <= 2 {
+                Err("device busy")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn test_retry_gives_up_after_max_attempts() {
+        let mut calls = 0;
+        let result: Result<(), _> = retry_with_backoff(3, Duration::ZERO, || {
+            calls += 1;
+            Err("device gone")
+        });
+        assert_eq!(result, Err("device gone"));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_device_chain_falls_through_to_third_selector() {
+        let devices = vec!["Built-in Microphone".to_string(), "USB Audio".to_string()];
+        let chain = [
+            DeviceSelector::Name("loopback".into()),
+            DeviceSelector::Monitor,
+            DeviceSelector::Default,
+            DeviceSelector::Interactive,
+        ];
+        let mut prompted = false;
+        let chosen =
+            resolve_device_chain(&chain, &devices, Some("Built-in Microphone"), None, || {
+                prompted = true;
+                None
+            });
+
+        assert_eq!(
+            chosen,
+            Some((&DeviceSelector::Default, "Built-in Microphone".to_string()))
+        );
+        assert!(!prompted, "later selectors must not run");
+    }
+
+    #[test]
+    fn test_device_chain_name_and_cancelled_chooser() {
+        let devices = vec![
+            "Monitor of Speakers".to_string(),
+            "BlackHole 2ch".to_string(),
+        ];
+        let chain = [
+            DeviceSelector::Interactive,
+            DeviceSelector::Name("blackhole".into()),
+        ];
+
+        let chosen = resolve_device_chain(&chain, &devices, None, None, || None);
+        assert_eq!(chosen.map(|(_, n)| n), Some("BlackHole 2ch".to_string()));
+        assert!(
+            resolve_device_chain(&[DeviceSelector::Default], &devices, None, None, || None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_device_chain_reaches_loopback_outputs() {
+        // Windows: a microphone input, then the outputs captured in loopback
+        let devices = vec![
+            "Microphone (USB Audio)".to_string(),
+            "Speakers (Realtek Audio)".to_string(),
+            "Headphones (USB Audio)".to_string(),
+        ];
+        let resolve = |chain: &[DeviceSelector]| {
+            resolve_device_chain(
+                chain,
+                &devices,
+                Some("Microphone (USB Audio)"),
+                Some("Speakers (Realtek Audio)"),
+                || None,
+            )
+            .map(|(_, name)| name)
+        };
+
+        // A name selector matches an output device, inputs first
+        assert_eq!(
+            resolve(&[DeviceSelector::Name("headphones".into())]),
+            Some("Headphones (USB Audio)".to_string())
+        );
+        assert_eq!(
+            resolve(&[DeviceSelector::Name("usb audio".into())]),
+            Some("Microphone (USB Audio)".to_string())
+        );
+        // Without a monitor device, a cancelled chooser falls back to
+        // capturing the default output rather than the microphone
+        assert_eq!(
+            resolve(DEFAULT_DEVICE_CHAIN),
+            Some("Speakers (Realtek Audio)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_device_selector_parse() {
+        assert_eq!("monitor".parse(), Ok(DeviceSelector::Monitor));
+        assert_eq!(
+            "name:BlackHole".parse(),
+            Ok(DeviceSelector::Name("BlackHole".into()))
+        );
+        assert!("name:".parse::<DeviceSelector>().is_err());
+        assert!("mic".parse::<DeviceSelector>().is_err());
+    }
+
+    #[test]
+    fn test_gap_detector_flags_long_interval() {
+        // 480-frame callbacks at 48 kHz arrive every 10 ms
+        let mut gaps = GapDetector::new(48000);
+        let ms = Duration::from_millis;
+
+        assert_eq!(gaps.observe(ms(0), 480), None);
+        assert_eq!(gaps.observe(ms(10), 480), None);
+        // Normal jitter is tolerated
+        assert_eq!(gaps.observe(ms(23), 480), None);
+        assert_eq!(gaps.observe(ms(31), 480), None);
+        // 50 ms between callbacks: 40 ms of audio went missing
+        assert_eq!(gaps.observe(ms(81), 480), Some(ms(40)));
+        assert_eq!(gaps.observe(ms(91), 480), None);
+    }
+}
@@ -0,0 +1,104 @@
+use clap::Parser;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wled_audio_server::bridge::{subnet_broadcast, Bridge, Relayed};
+use wled_audio_server::packet::{parse_target, SyncVersion, UdpSender};
+
+/// How often the relay loop checks for Ctrl+C while no packets arrive.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often forwarding statistics are printed.
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Parser)]
+#[command(
+    name = "bridge",
+    about = "Relay WLED AudioSync packets to controllers across subnets"
+)]
+struct Args {
+    /// Address to receive AudioSync packets on
+    #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:11988")]
+    listen: SocketAddr,
+
+    /// Port for targets and subnets given without one
+    #[arg(short, long, default_value_t = 11988)]
+    port: u16,
+
+    /// Forward to this controller (IP or IP:PORT, append @v1 for old WLED builds), may be repeated
+    #[arg(long = "target", value_name = "ADDR", value_parser = parse_target)]
+    targets: Vec<(IpAddr, Option<u16>, SyncVersion)>,
+
+    /// Forward to the broadcast address of this subnet (e.g. 192.168.2.0/24), may be repeated
+    #[arg(long = "subnet", value_name = "CIDR", value_parser = subnet_broadcast)]
+    subnets: Vec<Ipv4Addr>,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.targets.is_empty() && args.subnets.is_empty() {
+        eprintln!("Error: give at least one --target or --subnet");
+        std::process::exit(2);
+    }
+
+    let unicast: Vec<SocketAddr> = args
+        .targets
+        .iter()
+        .map(|&(ip, port, _)| SocketAddr::new(ip, port.unwrap_or(args.port)))
+        .chain(
+            args.subnets
+                .iter()
+                .map(|&ip| SocketAddr::new(ip.into(), args.port)),
+        )
+        .collect();
+    let mut sender = match UdpSender::with_targets(args.port, &unicast, false) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error creating UDP socket: {e}");
+            std::process::exit(1);
+        }
+    };
+    for (addr, &(.., version)) in unicast.iter().zip(&args.targets) {
+        sender.set_target_version(*addr, version);
+    }
+
+    let mut bridge = match Bridge::bind(args.listen, sender, POLL_INTERVAL) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Error listening on {}: {e}", args.listen);
+            std::process::exit(1);
+        }
+    };
+    let targets: Vec<String> = bridge.targets().iter().map(|t| t.to_string()).collect();
+    println!("Listening on {}", args.listen);
+    println!("Forwarding to: {}", targets.join(", "));
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || {
+        r.store(false, Ordering::SeqCst);
+    })
+    .expect("Failed to set Ctrl+C handler");
+
+    let (mut forwarded, mut rejected) = (0u64, 0u64);
+    let mut last_stats = Instant::now();
+    while running.load(Ordering::SeqCst) {
+        match bridge.relay_one() {
+            Ok(Relayed::Forwarded(_)) => forwarded += 1,
+            Ok(Relayed::Rejected(src)) => {
+                rejected += 1;
+                if rejected == 1 {
+                    eprintln!("Warning: dropping non-AudioSync datagrams (first from {src})");
+                }
+            }
+            Ok(Relayed::Echo | Relayed::Idle) => {}
+            Err(e) => eprintln!("Forward error: {e}"),
+        }
+        if last_stats.elapsed() >= STATS_INTERVAL {
+            println!("Forwarded {forwarded} packets, dropped {rejected} invalid");
+            last_stats = Instant::now();
+        }
+    }
+    println!("\nStopped. Forwarded {forwarded} packets, dropped {rejected} invalid");
+}
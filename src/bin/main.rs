@@ -1,11 +1,259 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::RecvTimeoutError;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use wled_audio_server::audio::{choose_input_device, open_capture_stream};
-use wled_audio_server::dsp::DspProcessor;
-use wled_audio_server::packet::{AudioSyncPacketV2, UdpSender};
+use wled_audio_server::analysis::{analyze_dir, csv_header};
+use wled_audio_server::audio::{
+    describe_input_devices, enumerate_devices, open_capture_stream, resolve_device_chain,
+    run_bounded, select_input_device, AudioError, CaptureOptions, CaptureStreamHandle,
+    DeviceSelector, DownmixMode, DropAdvisor, ReconnectPolicy, RestartPolicy, DEFAULT_DEVICE_CHAIN,
+    SHUTDOWN_TIMEOUT,
+};
+use wled_audio_server::diagnostics;
+use wled_audio_server::dsp::{
+    AgcMode, BeatMode, BinScale, Binning, DspConfig, DspFrame, DspProcessor, LevelMeter, LevelMode,
+    LevelQuality, PeakGate, PresenceBoost, WindowFunction, ZeroCrossingConvention,
+};
+use wled_audio_server::expr::BinExpr;
+#[cfg(feature = "msgpack")]
+use wled_audio_server::msgpack::{parse_msgpack_target, MsgpackSink, MsgpackTarget};
+#[cfg(unix)]
+use wled_audio_server::packet::UnixSocketSender;
+use wled_audio_server::packet::{
+    beat_gate, check_port, check_targets, parse_static_bins, parse_target, probe_udp,
+    static_packet, AudioSyncPacketV2, BeatIdle, FrameSink, SyncVersion, TargetCache, TokenBucket,
+    UdpSender, WledProtocol,
+};
+#[cfg(feature = "http")]
+use wled_audio_server::preset::{parse_http_host, PresetTrigger};
+use wled_audio_server::profile::{GainProfile, LatencyPreset, Sensitivity, BUILTIN_PROFILES};
+use wled_audio_server::recorder::WavRecorder;
+use wled_audio_server::replay::render_packets;
+use wled_audio_server::resample::Resampler;
+use wled_audio_server::schedule::{QuietHours, TimeRange};
+use wled_audio_server::source::{decode_wav, AudioSource, CaptureSource, WavSource};
+use wled_audio_server::wav::{read_wav, WavAudio};
+
+/// Packet destination selected with `--output`.
+#[derive(Clone)]
+enum Output {
+    /// UDP broadcast on `--port` (default)
+    Udp,
+    /// Unix datagram socket at the given path
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Non-beat behavior for `--beat-only`.
+#[derive(Clone, Copy, ValueEnum)]
+enum BeatOnly {
+    /// Send nothing between beats
+    Suppress,
+    /// Send zeroed packets between beats
+    Zero,
+}
+
+/// Channel fold for `--downmix-mode`.
+#[derive(Clone, Copy, ValueEnum)]
+enum DownmixArg {
+    /// Mean of all channels (default)
+    Average,
+    /// Loudest channel per sample, keeping its sign
+    Max,
+}
+
+impl From<DownmixArg> for DownmixMode {
+    fn from(mode: DownmixArg) -> Self {
+        match mode {
+            DownmixArg::Average => DownmixMode::Average,
+            DownmixArg::Max => DownmixMode::Max,
+        }
+    }
+}
+
+/// Bin spacing for `--bin-scale`.
+#[derive(Clone, Copy, ValueEnum)]
+enum BinScaleArg {
+    /// Equal frequency ratios (default)
+    Log,
+    /// Equal widths on the Bark critical-band scale
+    Bark,
+}
+
+impl From<BinScaleArg> for BinScale {
+    fn from(scale: BinScaleArg) -> Self {
+        match scale {
+            BinScaleArg::Log => BinScale::Log,
+            BinScaleArg::Bark => BinScale::Bark,
+        }
+    }
+}
+
+/// Level measure for `--level-mode`.
+#[derive(Clone, Copy, ValueEnum)]
+enum LevelModeArg {
+    /// Peak absolute sample (default)
+    Peak,
+    /// Root mean square of the frame
+    Rms,
+}
+
+impl From<LevelModeArg> for LevelMode {
+    fn from(mode: LevelModeArg) -> Self {
+        match mode {
+            LevelModeArg::Peak => LevelMode::Peak,
+            LevelModeArg::Rms => LevelMode::Rms,
+        }
+    }
+}
+
+/// Beat detector for `--beat-mode`.
+#[derive(Clone, Copy, ValueEnum)]
+enum BeatModeArg {
+    /// Bass-band energy threshold (default)
+    Energy,
+    /// Spectral-flux onsets across the whole range (catches snares, ignores held bass)
+    Flux,
+}
+
+impl From<BeatModeArg> for BeatMode {
+    fn from(mode: BeatModeArg) -> Self {
+        match mode {
+            BeatModeArg::Energy => BeatMode::Energy,
+            BeatModeArg::Flux => BeatMode::SpectralFlux,
+        }
+    }
+}
+
+/// AGC adaptation for `--agc-mode`.
+#[derive(Clone, Copy, ValueEnum)]
+enum AgcModeArg {
+    /// Adapt to louder and quieter input (default)
+    Symmetric,
+    /// Only compress loud peaks; never boost quiet passages
+    AttackOnly,
+    /// Only boost quiet passages; never compress peaks
+    ReleaseOnly,
+}
+
+/// Analysis window for `--window`.
+#[derive(Clone, Copy, ValueEnum)]
+enum WindowArg {
+    /// Narrow main lobe, good general-purpose choice
+    Hann,
+    /// Like Hann with lower first sidelobe
+    Hamming,
+    /// Very low leakage, wider main lobe than Hann
+    BlackmanHarris,
+    /// Most accurate amplitudes, widest main lobe (default)
+    Flattop,
+    /// No windowing: sharpest peaks, most leakage
+    Rectangular,
+}
+
+impl From<WindowArg> for WindowFunction {
+    fn from(window: WindowArg) -> Self {
+        match window {
+            WindowArg::Hann => WindowFunction::Hann,
+            WindowArg::Hamming => WindowFunction::Hamming,
+            WindowArg::BlackmanHarris => WindowFunction::BlackmanHarris,
+            WindowArg::Flattop => WindowFunction::FlatTop,
+            WindowArg::Rectangular => WindowFunction::Rectangular,
+        }
+    }
+}
+
+impl From<AgcModeArg> for AgcMode {
+    fn from(mode: AgcModeArg) -> Self {
+        match mode {
+            AgcModeArg::Symmetric => AgcMode::Symmetric,
+            AgcModeArg::AttackOnly => AgcMode::AttackOnly,
+            AgcModeArg::ReleaseOnly => AgcMode::ReleaseOnly,
+        }
+    }
+}
+
+impl From<BeatOnly> for BeatIdle {
+    fn from(mode: BeatOnly) -> Self {
+        match mode {
+            BeatOnly::Suppress => BeatIdle::Suppress,
+            BeatOnly::Zero => BeatIdle::Zero,
+        }
+    }
+}
+
+fn parse_freq_range(s: &str) -> Result<(f32, f32), String> {
+    let (lo, hi) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected LO-HI in Hz, got '{s}'"))?;
+    let lo: f32 = lo.trim().parse().map_err(|e| format!("invalid LO: {e}"))?;
+    let hi: f32 = hi.trim().parse().map_err(|e| format!("invalid HI: {e}"))?;
+    if !lo.is_finite() || !hi.is_finite() || lo < 0.0 {
+        return Err(format!(
+            "expected a range of finite, non-negative frequencies, got '{s}'"
+        ));
+    }
+    if lo >= hi {
+        return Err(format!("LO ({lo}) must be below HI ({hi})"));
+    }
+    Ok((lo, hi))
+}
+
+fn parse_weight(s: &str) -> Result<f32, String> {
+    let w: f32 = s.parse().map_err(|e| format!("invalid weight: {e}"))?;
+    if !(0.0..=1.0).contains(&w) {
+        return Err(format!("weight must be between 0 and 1, got {w}"));
+    }
+    Ok(w)
+}
+
+fn parse_packet_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("invalid rate: {e}"))?;
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(format!("rate must be a positive number, got {rate}"));
+    }
+    Ok(rate)
+}
+
+fn parse_gain(s: &str) -> Result<f32, String> {
+    let gain: f32 = s.parse().map_err(|e| format!("invalid gain: {e}"))?;
+    if !gain.is_finite() {
+        return Err(format!("gain must be a finite number, got {s}"));
+    }
+    Ok(gain)
+}
+
+#[cfg(feature = "http")]
+fn parse_interval(s: &str) -> Result<f64, String> {
+    let secs: f64 = s.parse().map_err(|e| format!("invalid interval: {e}"))?;
+    if Duration::try_from_secs_f64(secs).is_err() {
+        return Err(format!(
+            "interval must be a non-negative number of seconds, got {s}"
+        ));
+    }
+    Ok(secs)
+}
+
+fn parse_profile(s: &str) -> Result<&'static GainProfile, String> {
+    GainProfile::builtin(s).ok_or_else(|| {
+        let names: Vec<_> = BUILTIN_PROFILES.iter().map(|p| p.name).collect();
+        format!("unknown profile '{s}' (available: {})", names.join(", "))
+    })
+}
+
+fn parse_output(s: &str) -> Result<Output, String> {
+    if s == "udp" {
+        return Ok(Output::Udp);
+    }
+    #[cfg(unix)]
+    if let Some(path) = s.strip_prefix("unix:") {
+        return Ok(Output::Unix(PathBuf::from(path)));
+    }
+    Err(format!("unsupported output '{s}' (expected 'udp' or 'unix:<path>')"))
+}
 
 #[derive(Parser)]
 #[command(
@@ -20,11 +268,390 @@ struct Args {
     /// Enable verbose debug output
     #[arg(short, long)]
     verbose: bool,
+
+    /// Directions the AGC adapts in
+    #[arg(long, value_enum, value_name = "MODE", default_value = "symmetric")]
+    agc_mode: AgcModeArg,
+
+    /// AGC attack weight (0..1): how fast the envelope widens for louder input [default: 0.75, ~15ms]
+    #[arg(long, value_name = "W", value_parser = parse_weight)]
+    agc_attack: Option<f32>,
+
+    /// AGC release weight (0..1): lower for slow ambient tracks, higher for percussive music [default: 0.10, ~200ms]
+    #[arg(long, value_name = "W", value_parser = parse_weight)]
+    agc_release: Option<f32>,
+
+    /// Fixed AGC floor (raw bin level mapped to 0); freezes the AGC
+    #[arg(long, requires = "agc_max")]
+    agc_min: Option<f32>,
+
+    /// Fixed AGC ceiling (raw bin level mapped to 255); freezes the AGC
+    #[arg(long, requires = "agc_min")]
+    agc_max: Option<f32>,
+
+    /// Disable the AGC and map raw bin levels to 0..255 as level * SCALE, so quiet passages stay dim
+    #[arg(long, value_name = "SCALE", conflicts_with_all = ["agc_min", "agc_max"])]
+    fixed_gain: Option<f32>,
+
+    /// Give each of the 16 bins its own AGC envelope so loud bass doesn't drown out treble detail
+    #[arg(long)]
+    per_bin_agc: bool,
+
+    /// Seed the AGC envelope from the first non-silent frame so startup output isn't saturated
+    #[arg(long, conflicts_with = "fixed_gain")]
+    agc_seed: bool,
+
+    /// Beat detection algorithm driving sampleAgc's peak flag
+    #[arg(long, value_enum, value_name = "MODE", default_value = "energy")]
+    beat_mode: BeatModeArg,
+
+    /// Frames to suppress beat detection for while the baseline settles
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    beat_warmup: usize,
+
+    /// Minimum milliseconds between beats, so one hit can't fire twice (0 = off)
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    beat_cooldown: u64,
+
+    /// Periodically print sample_raw vs sample_smth with the smoothing time constant
+    #[arg(long)]
+    debug_smoothing: bool,
+
+    /// Clamp zeroCrossingCount to this maximum (WLED itself produces 0..=511)
+    #[arg(long, value_name = "N")]
+    max_zero_crossings: Option<u16>,
+
+    /// Count zero crossings with the original sample >= 0 test instead of WLED's sign-bit convention
+    #[arg(long)]
+    legacy_zero_crossings: bool,
+
+    /// Only send full packets on detected beats; between beats suppress or send zeros
+    #[arg(long, value_enum, value_name = "IDLE")]
+    beat_only: Option<BeatOnly>,
+
+    /// Snap sampleSmth to sampleRaw when they differ by more than DELTA (0..255)
+    #[arg(long, value_name = "DELTA")]
+    fast_attack: Option<f32>,
+
+    /// Peak level (0..1) below which a frame counts as silent
+    #[arg(long, value_name = "LEVEL")]
+    silence_threshold: Option<f32>,
+
+    /// Peak level (0..1) needed to leave silence again; set above --silence-threshold for hysteresis
+    #[arg(long, value_name = "LEVEL")]
+    silence_exit: Option<f32>,
+
+    /// Detect silence on the AC component so DC-biased inputs still go silent
+    #[arg(long)]
+    ac_silence: bool,
+
+    /// Subtract each frame's mean before analysis, for DC-biased inputs
+    #[arg(long)]
+    remove_dc: bool,
+
+    /// Pre-emphasis coefficient for the FFT input (y = x - COEF * x_prev), lifting the treble (0..1, 0 disables)
+    #[arg(long, value_name = "COEF", default_value_t = 0.0, value_parser = parse_weight)]
+    pre_emphasis: f32,
+
+    /// Boost the bass (beat band) bins by GAIN on each beat, releasing over a few frames
+    #[arg(long, value_name = "GAIN")]
+    beat_punch: Option<f32>,
+
+    /// Force transmitted bins below N to 0 so residual noise doesn't leave LEDs faintly lit (0 disables)
+    #[arg(long, value_name = "N")]
+    bin_floor: Option<u8>,
+
+    /// Reshape each bin with a formula over x (level, 1.0 = full scale) and i (bin index), e.g. "x^1.5"
+    #[arg(long, value_name = "EXPR")]
+    bin_expr: Option<BinExpr>,
+
+    /// Send an all-zero frame whenever FFT_Magnitude is below MAG, gating faint background noise
+    #[arg(long, value_name = "MAG")]
+    min_magnitude: Option<f32>,
+
+    /// Dim all bins by DEPTH (0..1) between beats, restoring them on each beat
+    #[arg(long, value_name = "DEPTH")]
+    beat_duck: Option<f32>,
+
+    /// Only update FFT_MajorPeak while the smoothed peak magnitude is at least MAG
+    #[arg(long, value_name = "MAG")]
+    peak_gate: Option<f32>,
+
+    /// Attack weight (0..1) of the --peak-gate magnitude reference; higher opens sooner
+    #[arg(long, value_name = "W", default_value_t = PeakGate::DEFAULT_ATTACK, requires = "peak_gate")]
+    peak_gate_attack: f32,
+
+    /// Release weight (0..1) of the --peak-gate magnitude reference; lower holds it open longer
+    #[arg(long, value_name = "W", default_value_t = PeakGate::DEFAULT_RELEASE, requires = "peak_gate")]
+    peak_gate_release: f32,
+
+    /// Hold FFT_MajorPeak for N frames after each beat so drum hits don't move it
+    #[arg(long, value_name = "N")]
+    transient_hold: Option<usize>,
+
+    /// Gain applied to the vocal presence bins (see --presence-range) before AGC
+    #[arg(long, value_name = "GAIN")]
+    presence_boost: Option<f32>,
+
+    /// Frequency range boosted by --presence-boost, in Hz
+    #[arg(long, value_name = "LO-HI", default_value = "1000-4000", value_parser = parse_freq_range)]
+    presence_range: (f32, f32),
+
+    /// Smooth the bins across frequency with a moving average N bins wide
+    #[arg(long, value_name = "N")]
+    spectral_smoothing: Option<usize>,
+
+    /// FFT length (power of two, 256-16384): smaller is lighter on CPU, larger resolves bass better
+    #[arg(long, value_name = "N", default_value_t = 2048)]
+    fft_size: usize,
+
+    /// Responsive preset: 1024-point FFT, 256-frame capture buffer, 2-chunk queue (~94 frames/s)
+    #[arg(long, conflicts_with_all = ["fft_size", "smooth"])]
+    low_latency: bool,
+
+    /// Steady preset: 4096-point FFT, 1024-frame capture buffer, 16-chunk queue (~23 frames/s)
+    #[arg(long, conflicts_with = "fft_size")]
+    smooth: bool,
+
+    /// Sample chunks queued between capture and the DSP: more rides out stalls without drops but adds latency [default: 8]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    queue_len: Option<u16>,
+
+    /// FFT analysis window; flattop reads levels best, hann and friends resolve the major peak better
+    #[arg(long, value_enum, value_name = "WINDOW", default_value = "flattop")]
+    window: WindowArg,
+
+    /// Compute the 16 bins with a constant-Q transform: sharper low bands, more CPU
+    #[arg(long)]
+    constant_q: bool,
+
+    /// Spacing of the 16 bins; bark follows the ear's critical bands (fewer bass bins, more mids)
+    #[arg(long, value_enum, value_name = "SCALE", default_value = "log")]
+    bin_scale: BinScaleArg,
+
+    /// Level behind sampleRaw/sampleSmth: peak, or rms for a steadier envelope on transient-heavy music
+    #[arg(long, value_enum, value_name = "MODE", default_value = "peak")]
+    level_mode: LevelModeArg,
+
+    /// Analyze at most N frames per second, skipping surplus audio to bound CPU
+    #[arg(long, value_name = "N")]
+    max_fps: Option<f32>,
+
+    /// Spectral tilt in dB/octave around 1 kHz (e.g. 3 to balance bass-heavy music)
+    #[arg(long, value_name = "DB", allow_negative_numbers = true)]
+    spectral_tilt: Option<f32>,
+
+    /// Input trim in dB applied before analysis (e.g. -6 for hot sources)
+    #[arg(long, value_name = "DB", allow_negative_numbers = true)]
+    input_gain: Option<f32>,
+
+    /// Master gain (0.01-100) on levels and bins before AGC; unlike --input-gain it never clips
+    #[arg(long, value_name = "FACTOR", value_parser = parse_gain)]
+    gain: Option<f32>,
+
+    /// Source profile bundling trim, AGC, EQ, and beat sensitivity (music, movies, games)
+    #[arg(long, value_name = "NAME", value_parser = parse_profile)]
+    profile: Option<&'static GainProfile>,
+
+    /// Overall reactivity 0-100 (50 = the defaults); sets input trim, AGC span floor, noise gate, and output gain together
+    #[arg(long, value_name = "0-100", value_parser = clap::value_parser!(u8).range(0..=100))]
+    sensitivity: Option<u8>,
+
+    /// Play a WAV file (8-32 bit PCM or 32-bit float) through the pipeline instead of capturing live audio
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["stereo", "reconnect", "restart_drop_rate"]
+    )]
+    input_file: Option<PathBuf>,
+
+    /// Feed --input-file as fast as possible instead of in real time
+    #[arg(long, requires = "input_file")]
+    no_realtime: bool,
+
+    /// Record the captured audio to a WAV file (two channels with --stereo)
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Device selector tried in order until one matches, may be repeated:
+    /// name:<TEXT>, monitor, interactive, or default (default: interactive, then monitor)
+    #[arg(long = "device", value_name = "SELECTOR")]
+    devices: Vec<DeviceSelector>,
+
+    /// Extra unicast target (IP or IP:PORT, append @v1 for old WLED builds), may be repeated
+    #[arg(long = "target", value_name = "ADDR", value_parser = parse_target)]
+    targets: Vec<(IpAddr, Option<u16>, SyncVersion)>,
+
+    /// Only send to --target addresses, skipping broadcast discovery
+    #[arg(long, requires = "targets")]
+    no_broadcast: bool,
+
+    /// Start from the broadcast targets cached in this file and re-discover in the background
+    #[arg(long, value_name = "PATH", conflicts_with = "no_broadcast")]
+    target_cache: Option<PathBuf>,
+
+    /// Cap the UDP send rate at N packets per second (token bucket); excess packets are skipped
+    #[arg(long, value_name = "N", value_parser = parse_packet_rate)]
+    max_pps: Option<f64>,
+
+    /// Packets that may be sent back to back under --max-pps
+    #[arg(long, value_name = "N", default_value_t = 5, requires = "max_pps")]
+    send_burst: u32,
+
+    /// Use a non-blocking UDP socket: packets that don't fit a full send buffer are dropped and counted
+    #[arg(long)]
+    nonblocking_send: bool,
+
+    /// Send this fixed spectrum (16 comma-separated values 0-255) instead of the analyzed audio
+    #[arg(long, value_name = "BINS", value_parser = parse_static_bins)]
+    static_bins: Option<[u8; 16]>,
+
+    /// Open the capture device at exactly this sample rate instead of its default
+    #[arg(long, value_name = "HZ")]
+    force_rate: Option<u32>,
+
+    /// Resample captured audio to this rate so the frequency bins don't shift with the device's native rate
+    #[arg(long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(8000..=192000))]
+    resample: Option<u32>,
+
+    /// Swap the left and right input channels (for interfaces wired in reverse)
+    #[arg(long)]
+    swap_channels: bool,
+
+    /// Capture only these input channels (1-based, comma-separated) instead of all of them
+    #[arg(
+        long,
+        value_name = "N,...",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    input_channels: Vec<u16>,
+
+    /// How multi-channel input is folded to mono
+    #[arg(long, value_enum, value_name = "MODE", default_value = "average")]
+    downmix_mode: DownmixArg,
+
+    /// Analyze left and right separately; the packet carries the left channel and MessagePack output adds the right bins
+    #[arg(long, conflicts_with = "downmix_mode")]
+    stereo: bool,
+
+    /// Local time range (HH:MM-HH:MM, may wrap past midnight) to stop sending in, may be repeated
+    #[arg(long = "quiet-hours", value_name = "RANGE")]
+    quiet_hours: Vec<TimeRange>,
+
+    /// Send zeroed packets during --quiet-hours instead of nothing
+    #[arg(long, requires = "quiet_hours")]
+    quiet_zero: bool,
+
+    /// Rebuild the capture stream when drops stay above N chunks/sec for --restart-after seconds
+    #[arg(long, value_name = "N")]
+    restart_drop_rate: Option<f64>,
+
+    /// Seconds the drop rate must stay above --restart-drop-rate before the stream is rebuilt
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 15,
+        requires = "restart_drop_rate"
+    )]
+    restart_after: u64,
+
+    /// Reopen the capture device (with backoff) when audio stops arriving, e.g. after a USB unplug or a PulseAudio restart
+    #[arg(long)]
+    reconnect: bool,
+
+    /// Seconds without audio before --reconnect reopens the device
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "reconnect"
+    )]
+    stall_timeout: u64,
+
+    /// Probe each --target before streaming and report whether it looks reachable
+    #[arg(long, requires = "targets")]
+    check_targets: bool,
+
+    /// Analyze every WAV in a directory (as fast as possible) and print a CSV summary per file, then exit
+    #[arg(long, value_name = "PATH")]
+    analyze_dir: Option<PathBuf>,
+
+    /// List the input devices with their default config and sample formats, then exit
+    #[arg(long)]
+    list_devices: bool,
+
+    /// Print OS, audio host, devices, network targets, and DSP settings for bug reports, then exit
+    #[arg(long)]
+    diagnose: bool,
+
+    /// Render a WAV file to a golden packet file (frame counter zeroed) and exit
+    #[arg(long, num_args = 2, value_names = ["WAV", "OUT"])]
+    golden: Option<Vec<PathBuf>>,
+
+    /// Packet output: 'udp' (broadcast) or 'unix:<path>'
+    #[arg(long, default_value = "udp", value_parser = parse_output)]
+    output: Output,
+
+    /// Also send every analyzed frame as MessagePack to 'udp:HOST:PORT' or 'tcp:HOST:PORT'
+    #[cfg(feature = "msgpack")]
+    #[arg(long, value_name = "DEST", value_parser = parse_msgpack_target)]
+    msgpack: Option<MsgpackTarget>,
+
+    /// Apply WLED preset ID (via the HTTP JSON API of --wled-host) on detected beats
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ID", requires = "wled_host")]
+    beat_preset: Option<u8>,
+
+    /// WLED instance (HOST or HOST:PORT) that --beat-preset talks to
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "HOST", value_parser = parse_http_host)]
+    wled_host: Option<SocketAddr>,
+
+    /// Minimum seconds between --beat-preset triggers
+    #[cfg(feature = "http")]
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 4.0,
+        value_parser = parse_interval,
+        requires = "beat_preset"
+    )]
+    beat_preset_interval: f64,
 }
 
 fn main() {
     let args = Args::parse();
 
+    if let Some(paths) = &args.golden {
+        if let Err(e) = write_golden(&paths[0], &paths[1]) {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(dir) = &args.analyze_dir {
+        if let Err(e) = print_dir_analysis(dir) {
+            eprintln!("Error reading {}: {e}", dir.display());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.list_devices {
+        for device in enumerate_devices() {
+            println!("{device}");
+        }
+        return;
+    }
+
+    if args.diagnose {
+        print_diagnostics(&args);
+        return;
+    }
+
     // Ctrl+C handler
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -33,53 +660,234 @@ fn main() {
     })
     .expect("Failed to set Ctrl+C handler");
 
-    let device_hint = choose_input_device();
+    let chain = if args.devices.is_empty() {
+        DEFAULT_DEVICE_CHAIN.to_vec()
+    } else {
+        args.devices.clone()
+    };
+    let device_hint = match args.input_file {
+        Some(_) => None,
+        None => select_input_device(&chain),
+    };
 
-    // Open audio capture
-    let (_stream, sample_rate, rx, drop_counter) = match open_capture_stream(device_hint.as_deref())
-    {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("Error: {e}");
-            std::process::exit(1);
+    // Open audio capture, or the file standing in for it. The stream is taken
+    // while it's rebuilt after a drop storm, and there's none for a file.
+    let (mut stream, mut source, mut drop_counter, mut gap_counter) = match &args.input_file {
+        Some(path) => match open_input_file(path, &args) {
+            Ok(source) => (None, source, Arc::default(), Arc::default()),
+            Err(e) => {
+                eprintln!("Error reading {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => match open_capture_stream(device_hint.as_deref(), &capture_options(&args)) {
+            Ok((stream, rate, rx, drops, gaps)) => {
+                let source: Box<dyn AudioSource> = Box::new(CaptureSource::new(rx, rate));
+                (Some(stream), source, drops, gaps)
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        },
+    };
+    let sample_rate = source.sample_rate();
+
+    let mut sender: Box<dyn FrameSink> = match &args.output {
+        Output::Udp => {
+            let unicast = unicast_targets(&args);
+            let mut ports: Vec<u16> = unicast.iter().map(SocketAddr::port).collect();
+            if !args.no_broadcast {
+                ports.push(args.port);
+            }
+            ports.sort_unstable();
+            ports.dedup();
+            for warning in ports
+                .into_iter()
+                .filter_map(|port| check_port(WledProtocol::AudioSync, port))
+            {
+                eprintln!("Warning: {warning}");
+            }
+            if args.check_targets {
+                for (addr, result) in check_targets(&unicast, probe_udp) {
+                    match result {
+                        Ok(()) => println!("Target {addr}: reachable"),
+                        Err(e) => eprintln!("Warning: target {addr} looks unreachable: {e}"),
+                    }
+                }
+            }
+            let mut sender = match udp_sender(&args, &unicast) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Error creating UDP socket: {e}");
+                    std::process::exit(1);
+                }
+            };
+            for (addr, &(.., version)) in unicast.iter().zip(&args.targets) {
+                sender.set_target_version(*addr, version);
+            }
+
+            let targets = sender
+                .targets()
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if args.no_broadcast {
+                println!("Sending to: {}", targets);
+            } else {
+                println!("Broadcasting to: {}", targets);
+            }
+            if args.nonblocking_send {
+                if let Err(e) = sender.set_nonblocking(true) {
+                    eprintln!("Warning: could not make the UDP socket non-blocking: {e}");
+                }
+            }
+            if let Some(rate) = args.max_pps {
+                sender.set_rate_limit(Some(TokenBucket::new(rate, args.send_burst)));
+                println!(
+                    "Send rate capped at {rate} packets/s (burst {})",
+                    args.send_burst
+                );
+            }
+            Box::new(sender)
+        }
+        #[cfg(unix)]
+        Output::Unix(path) => {
+            let sender = match UnixSocketSender::connect(path) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Error connecting to {}: {e}", path.display());
+                    std::process::exit(1);
+                }
+            };
+            println!("Sending to Unix socket: {}", path.display());
+            Box::new(sender)
         }
     };
 
-    // UDP sender
-    let mut sender = match UdpSender::new(args.port) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error creating UDP socket: {e}");
-            std::process::exit(1);
+    #[cfg(feature = "msgpack")]
+    let mut msgpack = args
+        .msgpack
+        .map(|target| match MsgpackSink::connect(target) {
+            Ok(sink) => {
+                println!("Sending MessagePack frames to: {target:?}");
+                sink
+            }
+            Err(e) => {
+                eprintln!("Error opening MessagePack output {target:?}: {e}");
+                std::process::exit(1);
+            }
+        });
+
+    #[cfg(feature = "http")]
+    let mut preset_trigger = match (args.beat_preset, args.wled_host) {
+        (Some(preset), Some(host)) => {
+            println!(
+                "Applying preset {preset} on {host} on beats (at most every {}s)",
+                args.beat_preset_interval
+            );
+            Some(PresetTrigger::new(
+                host,
+                preset,
+                Duration::from_secs_f64(args.beat_preset_interval),
+            ))
         }
+        _ => None,
     };
 
-    let targets = sender
-        .targets()
-        .iter()
-        .map(|addr| addr.to_string())
-        .collect::<Vec<_>>()
-        .join(", ");
-    println!("Broadcasting to: {}", targets);
     if args.verbose {
         println!("Verbose mode enabled");
-        println!(
-            "DSP: FFT size 2048, 50% overlap, ~{:.1} frames/sec",
-            sample_rate as f32 / 1024.0
-        );
     }
+    // Stereo capture delivers interleaved left/right pairs
+    let channels = if args.stereo { 2 } else { 1 };
+    let mut recorder = args.record.as_ref().map(|path| {
+        match WavRecorder::with_channels(path, sample_rate, channels) {
+            Ok(r) => {
+                println!("Recording to: {}", path.display());
+                r
+            }
+            Err(e) => {
+                eprintln!("Error creating recording {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+    });
     println!("Press Ctrl+C to stop.");
 
-    let mut dsp = DspProcessor::new(sample_rate);
+    let mut dsp = build_dsp(&args, sample_rate);
+    // Right channel in --stereo mode; `dsp` then analyzes the left
+    let mut right_dsp = args.stereo.then(|| build_dsp(&args, sample_rate));
+    if args.verbose {
+        let (fft_size, advance) = (dsp.config().fft_size, dsp.advance());
+        println!(
+            "DSP: FFT size {fft_size}, advance {advance} ({:.0}% overlap), ~{:.1} frames/sec",
+            100.0 * fft_size.saturating_sub(advance) as f32 / fft_size as f32,
+            sample_rate as f32 / advance as f32
+        );
+        let (attack, release) = dsp.agc_time_constants();
+        println!(
+            "AGC: attack ~{:.0}ms, release ~{:.0}ms",
+            attack * 1000.0,
+            release * 1000.0
+        );
+    }
+    if let Some(profile) = args.profile {
+        println!("Using profile: {}", profile.name);
+    }
+    if let Some(preset) = latency_preset(&args) {
+        println!(
+            "Latency preset: {} (FFT {}, {}-frame capture buffer, {}-chunk queue)",
+            preset.name,
+            preset.fft_size,
+            preset.buffer_frames,
+            args.queue_len.map_or(preset.queue_len, usize::from)
+        );
+    }
+    if let (Some(min), Some(max)) = (args.agc_min, args.agc_max) {
+        println!("AGC frozen at {min:.2}..{max:.2}");
+    }
+    if let Some(scale) = args.fixed_gain {
+        println!("AGC disabled, fixed gain {scale}");
+    }
     let mut last_drop_check = Instant::now();
     let mut last_drop_count: u64 = 0;
+    let mut last_gap_count: u64 = 0;
+    let mut last_would_block: u64 = 0;
+    let mut drop_advisor = DropAdvisor::new();
+    let mut restart_policy = args
+        .restart_drop_rate
+        .map(|rate| RestartPolicy::new(rate, Duration::from_secs(args.restart_after)));
+    let mut reconnect = args
+        .reconnect
+        .then(|| ReconnectPolicy::new(Duration::from_secs(args.stall_timeout), Instant::now()));
+    // Drops and gaps counted by streams that have since been rebuilt
+    let mut earlier_drops: u64 = 0;
+    let mut earlier_gaps: u64 = 0;
+    // ~5 seconds of frames at 48kHz
+    let mut level_meter = LevelMeter::new(250);
+    let mut last_level: Option<LevelQuality> = None;
     let mut packet_count: u64 = 0;
     let mut last_verbose_log = Instant::now();
+    let mut last_smoothing_log = Instant::now();
+    let quiet_hours = QuietHours::new(args.quiet_hours.clone());
+    if !args.quiet_hours.is_empty() {
+        let ranges: Vec<String> = args.quiet_hours.iter().map(|r| r.to_string()).collect();
+        println!("Quiet hours: {}", ranges.join(", "));
+    }
+    let mut quiet = quiet_hours.is_quiet_now();
+    if quiet {
+        println!("Inside quiet hours; output paused");
+    }
+    let mut last_quiet_check = Instant::now();
 
     // Main loop
     while running.load(Ordering::SeqCst) {
-        match rx.recv_timeout(Duration::from_millis(100)) {
+        let (samples, ended) = match source.recv_timeout(Duration::from_millis(100)) {
             Ok(samples) => {
+                if let Some(policy) = reconnect.as_mut() {
+                    policy.alive(Instant::now());
+                }
                 if args.verbose && last_verbose_log.elapsed() >= Duration::from_millis(500) {
                     println!(
                         "[Verbose] Received {} samples, buffer at {} samples",
@@ -89,63 +897,531 @@ fn main() {
                     last_verbose_log = Instant::now();
                 }
 
-                let frames = dsp.push_samples(&samples);
-                for frame in frames {
-                    let pkt = AudioSyncPacketV2 {
-                        sample_raw: frame.sample_raw,
-                        sample_smth: frame.sample_smth,
-                        sample_peak: frame.sample_peak,
-                        fft_result: frame.fft_result,
-                        zero_crossing_count: frame.zero_crossing_count,
-                        fft_magnitude: frame.fft_magnitude,
-                        fft_major_peak: frame.fft_major_peak,
-                    };
-                    if let Err(e) = sender.send(&pkt) {
-                        eprintln!("UDP send error: {e}");
-                    } else if args.verbose {
-                        packet_count += 1;
-                        if packet_count.is_multiple_of(100) {
-                            println!(
-                                "[Verbose] Sent packet #{}: raw={:.1}, smth={:.1}, peak={}, mag={:.1}, freq={:.0}Hz, bins=[{},{},{},...]",
-                                packet_count,
-                                frame.sample_raw,
-                                frame.sample_smth,
-                                frame.sample_peak,
-                                frame.fft_magnitude,
-                                frame.fft_major_peak,
-                                frame.fft_result[0],
-                                frame.fft_result[1],
-                                frame.fft_result[2],
-                            );
-                        }
+                if let Some(rec) = recorder.as_mut() {
+                    if let Err(e) = rec.write_samples(&samples) {
+                        eprintln!("Recording error: {e}; recording stopped");
+                        recorder = None;
                     }
                 }
-            }
-            Err(RecvTimeoutError::Timeout) => {
-                // Check for dropped frames every 5 seconds
-                if last_drop_check.elapsed() >= Duration::from_secs(5) {
-                    let current_drops = drop_counter.load(Ordering::Relaxed);
-                    let new_drops = current_drops - last_drop_count;
-                    if new_drops > 0 {
-                        eprintln!(
-                            "Warning: Dropped {} audio chunks in the last 5 seconds (total: {})",
-                            new_drops, current_drops
+
+                if last_quiet_check.elapsed() >= Duration::from_secs(1) {
+                    let now_quiet = quiet_hours.is_quiet_now();
+                    if now_quiet != quiet {
+                        println!(
+                            "{}",
+                            if now_quiet {
+                                "Quiet hours started; output paused"
+                            } else {
+                                "Quiet hours ended; output resumed"
+                            }
                         );
+                        quiet = now_quiet;
+                    }
+                    last_quiet_check = Instant::now();
+                }
+
+                (Some(samples), false)
+            }
+            Err(RecvTimeoutError::Timeout) => (None, false),
+            // The stream is gone; wait for --reconnect to bring it back
+            Err(RecvTimeoutError::Disconnected) if reconnect.is_some() => {
+                std::thread::sleep(Duration::from_millis(100));
+                (None, false)
+            }
+            Err(RecvTimeoutError::Disconnected) => (None, true),
+        };
+
+        let smoothing = (dsp.smoothing_factor(), dsp.smoothing_time_constant());
+        let frames: Box<dyn Iterator<Item = DspFrame>> = match (&samples, right_dsp.as_mut()) {
+            (Some(samples), Some(right)) => Box::new(dsp.drain_stereo_frames(right, samples)),
+            (Some(samples), None) => Box::new(dsp.drain_frames(samples)),
+            // The file has ended; analyze its final partial window too. Live
+            // capture discards a partial window when the stream goes away.
+            (None, right) if ended && args.input_file.is_some() => Box::new(
+                dsp.finish()
+                    .map(|mut frame| {
+                        if let Some(right) = right {
+                            frame.fft_result_right = right.finish().map(|r| r.fft_result);
+                        }
+                        frame
+                    })
+                    .into_iter(),
+            ),
+            (None, _) => Box::new(std::iter::empty()),
+        };
+        for frame in frames {
+            level_meter.observe(frame.sample_raw);
+            if quiet {
+                if args.quiet_zero {
+                    if let Err(e) = sender.send(&static_packet(&[0; 16])) {
+                        eprintln!("Send error: {e}");
                     }
-                    last_drop_count = current_drops;
-                    last_drop_check = Instant::now();
                 }
                 continue;
             }
-            Err(RecvTimeoutError::Disconnected) => break,
+            #[cfg(feature = "http")]
+            if let Some(trigger) = preset_trigger.as_mut() {
+                trigger.on_frame(frame.sample_peak);
+            }
+            #[cfg(feature = "msgpack")]
+            if let Some(sink) = msgpack.as_mut() {
+                if let Err(e) = sink.send(&frame) {
+                    eprintln!("MessagePack send error: {e}");
+                }
+            }
+            if args.debug_smoothing && last_smoothing_log.elapsed() >= Duration::from_millis(250) {
+                println!(
+                    "[Smoothing] raw={:6.1} smth={:6.1} lag={:+6.1} (factor {:.2}, tau {:.0}ms)",
+                    frame.sample_raw,
+                    frame.sample_smth,
+                    frame.sample_smth - frame.sample_raw,
+                    smoothing.0,
+                    smoothing.1 * 1000.0,
+                );
+                last_smoothing_log = Instant::now();
+            }
+            let pkt = match (&args.static_bins, args.beat_only) {
+                (Some(bins), _) => static_packet(bins),
+                (None, Some(idle)) => match beat_gate(&frame, idle.into()) {
+                    Some(pkt) => pkt,
+                    None => continue,
+                },
+                (None, None) => AudioSyncPacketV2::from(&frame),
+            };
+            if let Err(e) = sender.send(&pkt) {
+                eprintln!("Send error: {e}");
+            } else if args.verbose {
+                packet_count += 1;
+                if packet_count.is_multiple_of(100) {
+                    println!(
+                        "[Verbose] Sent packet #{}: raw={:.1}, smth={:.1}, peak={}, mag={:.1}, freq={:.0}Hz, bins=[{},{},{},...]",
+                        packet_count,
+                        frame.sample_raw,
+                        frame.sample_smth,
+                        frame.sample_peak,
+                        frame.fft_magnitude,
+                        frame.fft_major_peak,
+                        frame.fft_result[0],
+                        frame.fft_result[1],
+                        frame.fft_result[2],
+                    );
+                }
+            }
+        }
+        if ended {
+            break;
+        }
+
+        if let Some(wait) = reconnect
+            .as_mut()
+            .and_then(|policy| policy.should_reconnect(Instant::now()))
+        {
+            eprintln!(
+                "Warning: no audio for {}s or more; reopening the capture device",
+                args.stall_timeout
+            );
+            match reopen_capture(&args, device_hint.as_deref(), sample_rate, &mut stream) {
+                Ok((new_stream, rate, rx, drops, gaps)) => {
+                    earlier_drops += drop_counter.load(Ordering::Relaxed);
+                    earlier_gaps += gap_counter.load(Ordering::Relaxed);
+                    stream = Some(new_stream);
+                    source = Box::new(CaptureSource::new(rx, rate));
+                    drop_counter = drops;
+                    gap_counter = gaps;
+                    last_drop_count = 0;
+                    last_gap_count = 0;
+                    if let Some(policy) = reconnect.as_mut() {
+                        policy.alive(Instant::now());
+                    }
+                    println!("Capture device reconnected");
+                }
+                Err(e) => eprintln!(
+                    "Warning: could not reopen the capture device: {e}; retrying in {}s",
+                    wait.as_secs()
+                ),
+            }
+        }
+
+        // Check for dropped frames every 5 seconds
+        let drop_window = last_drop_check.elapsed();
+        if drop_window >= Duration::from_secs(5) {
+            let current_drops = drop_counter.load(Ordering::Relaxed);
+            let new_drops = current_drops - last_drop_count;
+            if new_drops > 0 {
+                eprintln!(
+                    "Warning: Dropped {} audio chunks in the last 5 seconds (total: {})",
+                    new_drops, current_drops
+                );
+            }
+            if let Some(advice) = drop_advisor.observe(new_drops, drop_window) {
+                eprintln!("Hint: {advice}");
+            }
+            last_drop_count = current_drops;
+
+            // Gaps in the capture timestamps are losses before the channel
+            let current_gaps = gap_counter.load(Ordering::Relaxed);
+            let new_gaps = current_gaps - last_gap_count;
+            if new_gaps > 0 {
+                eprintln!(
+                    "Warning: Detected {new_gaps} capture gaps (driver underruns) in the last 5 seconds (total: {current_gaps})"
+                );
+            }
+            last_gap_count = current_gaps;
+
+            let would_block = sender.would_block_drops();
+            if would_block > last_would_block {
+                eprintln!(
+                    "Warning: Dropped {} packets on a full send buffer in the last 5 seconds (total: {would_block})",
+                    would_block - last_would_block
+                );
+            }
+            last_would_block = would_block;
+            last_drop_check = Instant::now();
+
+            if restart_policy
+                .as_mut()
+                .is_some_and(|policy| policy.observe(new_drops, drop_window))
+            {
+                eprintln!(
+                    "Warning: drop rate stayed above {:.1} chunks/sec for {}s; rebuilding the capture stream",
+                    args.restart_drop_rate.unwrap_or_default(),
+                    args.restart_after
+                );
+                match reopen_capture(&args, device_hint.as_deref(), sample_rate, &mut stream) {
+                    Ok((new_stream, rate, rx, drops, gaps)) => {
+                        earlier_drops += current_drops;
+                        earlier_gaps += current_gaps;
+                        stream = Some(new_stream);
+                        source = Box::new(CaptureSource::new(rx, rate));
+                        drop_counter = drops;
+                        gap_counter = gaps;
+                        last_drop_count = 0;
+                        last_gap_count = 0;
+                        println!("Capture stream rebuilt");
+                    }
+                    // --reconnect keeps retrying with backoff once the stall
+                    // timeout passes
+                    Err(e) if reconnect.is_some() => {
+                        eprintln!("Warning: could not rebuild the capture stream: {e}")
+                    }
+                    Err(e) => {
+                        eprintln!("Error: could not rebuild the capture stream: {e}");
+                        break;
+                    }
+                }
+            }
+
+            // Report the input level whenever its verdict changes
+            let level = level_meter.verdict();
+            if level != last_level {
+                match level {
+                    Some(LevelQuality::TooQuiet) => {
+                        println!("Input level: too quiet (raise --input-gain)")
+                    }
+                    Some(LevelQuality::Clipping) => {
+                        println!("Input level: clipping (lower --input-gain)")
+                    }
+                    Some(quality) => println!("Input level: {quality}"),
+                    None => {}
+                }
+                last_level = level;
+            }
+        }
+    }
+
+    // Stop capture; some drivers block in drop, so don't wait forever
+    if run_bounded(SHUTDOWN_TIMEOUT, move || drop(stream)).is_none() {
+        eprintln!(
+            "Warning: audio stream did not shut down within {}s; exiting anyway",
+            SHUTDOWN_TIMEOUT.as_secs()
+        );
+    }
+
+    // Rewrite the WAV header so its lengths match the recorded data
+    if let Some(rec) = recorder {
+        let seconds = rec.frames_written() as f32 / sample_rate as f32;
+        match rec.finalize() {
+            Ok(()) => println!("Recorded {seconds:.1}s of audio"),
+            Err(e) => eprintln!("Error finalizing recording: {e}"),
         }
     }
 
     // Final drop count report
-    let total_drops = drop_counter.load(Ordering::Relaxed);
+    let total_drops = earlier_drops + drop_counter.load(Ordering::Relaxed);
     if total_drops > 0 {
         eprintln!("Total audio chunks dropped during session: {}", total_drops);
     }
+    let total_gaps = earlier_gaps + gap_counter.load(Ordering::Relaxed);
+    if total_gaps > 0 {
+        eprintln!("Total capture gaps during session: {total_gaps}");
+    }
+    let would_block = sender.would_block_drops();
+    if would_block > 0 {
+        eprintln!("Total packets dropped on a full send buffer: {would_block}");
+    }
+    #[cfg(feature = "msgpack")]
+    if let Some(dropped) = msgpack.as_ref().map(MsgpackSink::dropped_frames) {
+        if dropped > 0 {
+            eprintln!("Total MessagePack frames dropped for a slow TCP consumer: {dropped}");
+        }
+    }
 
     println!("\nShutting down.");
 }
+
+/// The `--low-latency` or `--smooth` preset, if one was given.
+fn latency_preset(args: &Args) -> Option<LatencyPreset> {
+    if args.low_latency {
+        Some(LatencyPreset::LOW_LATENCY)
+    } else if args.smooth {
+        Some(LatencyPreset::SMOOTH)
+    } else {
+        None
+    }
+}
+
+/// Reads `--input-file`, resampled to `--resample` if given, as the audio source.
+fn open_input_file(path: &std::path::Path, args: &Args) -> std::io::Result<Box<dyn AudioSource>> {
+    let mut audio = decode_wav(path)?;
+    if let Some(rate) = args.resample.filter(|&rate| rate != audio.sample_rate) {
+        let mut resampler = Resampler::new(audio.sample_rate, rate, 1);
+        audio = WavAudio {
+            sample_rate: rate,
+            samples: resampler.process(&audio.samples).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "decoded audio isn't a whole number of frames for the resampler",
+                )
+            })?,
+        };
+    }
+    let source = WavSource::new(audio, !args.no_realtime);
+    println!(
+        "Playing {} ({:.1}s at {} Hz{})",
+        path.display(),
+        source.duration().as_secs_f64(),
+        source.sample_rate(),
+        if args.no_realtime { ", unpaced" } else { "" }
+    );
+    Ok(Box::new(source))
+}
+
+/// Tears down `stream` and opens the capture device again at the rate the DSP
+/// and recorder were set up for.
+fn reopen_capture(
+    args: &Args,
+    device_hint: Option<&str>,
+    sample_rate: u32,
+    stream: &mut Option<cpal::Stream>,
+) -> Result<CaptureStreamHandle, AudioError> {
+    if let Some(old) = stream.take() {
+        if run_bounded(SHUTDOWN_TIMEOUT, move || drop(old)).is_none() {
+            eprintln!("Warning: old audio stream did not shut down; abandoning it");
+        }
+    }
+    // With --resample that rate no longer depends on the device's
+    let options = CaptureOptions {
+        force_rate: match args.resample {
+            Some(_) => args.force_rate,
+            None => Some(sample_rate),
+        },
+        ..capture_options(args)
+    };
+    open_capture_stream(device_hint, &options)
+}
+
+/// Capture settings from the channel, rate, and latency flags.
+fn capture_options(args: &Args) -> CaptureOptions {
+    let options = CaptureOptions {
+        swap_channels: args.swap_channels,
+        channels: args
+            .input_channels
+            .iter()
+            .map(|&n| usize::from(n) - 1)
+            .collect(),
+        downmix_mode: if args.stereo {
+            DownmixMode::Stereo
+        } else {
+            args.downmix_mode.into()
+        },
+        force_rate: args.force_rate,
+        resample_to: args.resample,
+        ..CaptureOptions::default()
+    };
+    let options = match latency_preset(args) {
+        Some(preset) => preset.capture_options(options),
+        None => options,
+    };
+    CaptureOptions {
+        queue_len: args.queue_len.map_or(options.queue_len, usize::from),
+        ..options
+    }
+}
+
+/// Creates the DSP processor with every tuning flag applied.
+fn build_dsp(args: &Args, sample_rate: u32) -> DspProcessor {
+    let config = match latency_preset(args)
+        .map(|preset| Ok(preset.dsp_config()))
+        .unwrap_or_else(|| DspConfig::for_fft_size(args.fft_size))
+    {
+        Ok(config) => DspConfig {
+            agc_attack: args.agc_attack.unwrap_or(config.agc_attack),
+            agc_release: args.agc_release.unwrap_or(config.agc_release),
+            silence_threshold: args.silence_threshold.unwrap_or(config.silence_threshold),
+            silence_exit_threshold: args
+                .silence_exit
+                .or(args.silence_threshold)
+                .unwrap_or(config.silence_exit_threshold),
+            window: args.window.into(),
+            fixed_gain: args.fixed_gain,
+            beat_mode: args.beat_mode.into(),
+            bin_scale: args.bin_scale.into(),
+            level_mode: args.level_mode.into(),
+            binning: if args.constant_q {
+                Binning::ConstantQ
+            } else {
+                Binning::Fft
+            },
+            ..config
+        },
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(2);
+        }
+    };
+    let mut dsp = DspProcessor::with_config(sample_rate, config);
+    // Profile first so that explicit flags below override its settings
+    if let Some(profile) = args.profile {
+        profile.apply(&mut dsp);
+    }
+    if let Some(level) = args.sensitivity {
+        Sensitivity::from_level(level).apply(&mut dsp);
+    }
+    if let Some(db) = args.input_gain {
+        dsp.set_input_gain_db(db);
+    }
+    if let Some(gain) = args.gain {
+        dsp.set_gain(gain);
+    }
+    dsp.set_agc_mode(args.agc_mode.into());
+    dsp.set_per_bin_agc(args.per_bin_agc);
+    dsp.set_agc_seed(args.agc_seed);
+    dsp.set_beat_warmup(args.beat_warmup);
+    dsp.set_beat_cooldown(Duration::from_millis(args.beat_cooldown));
+    dsp.set_zero_crossing_cap(args.max_zero_crossings);
+    if args.legacy_zero_crossings {
+        dsp.set_zero_crossing_convention(ZeroCrossingConvention::Legacy);
+    }
+    dsp.set_fast_attack(args.fast_attack);
+    dsp.set_ac_silence(args.ac_silence);
+    dsp.set_remove_dc(args.remove_dc);
+    dsp.set_pre_emphasis(args.pre_emphasis);
+    dsp.set_beat_punch(args.beat_punch);
+    dsp.set_beat_duck(args.beat_duck);
+    if let Some(floor) = args.bin_floor {
+        dsp.set_bin_floor(floor);
+    }
+    if let Some(expr) = args.bin_expr.clone() {
+        dsp.set_bin_transform(Some(Box::new(move |i, x| expr.eval(x, i))));
+    }
+    dsp.set_min_magnitude(args.min_magnitude);
+    dsp.set_peak_gate(args.peak_gate.map(|min_magnitude| PeakGate {
+        min_magnitude,
+        attack: args.peak_gate_attack,
+        release: args.peak_gate_release,
+    }));
+    if let Some(frames) = args.transient_hold {
+        dsp.set_transient_hold(frames);
+    }
+    if let Some(gain) = args.presence_boost {
+        dsp.set_presence_boost(Some(PresenceBoost {
+            freq_lo: args.presence_range.0,
+            freq_hi: args.presence_range.1,
+            gain,
+        }));
+    }
+    dsp.set_max_frame_rate(args.max_fps);
+    if let Some(width) = args.spectral_smoothing {
+        dsp.set_spectral_smoothing(width);
+    }
+    if let Some(tilt) = args.spectral_tilt {
+        dsp.set_spectral_tilt(tilt);
+    }
+    if let (Some(min), Some(max)) = (args.agc_min, args.agc_max) {
+        dsp.set_agc_range(min, max);
+    }
+    dsp
+}
+
+/// Resolves `--target` entries to addresses, defaulting to `--port`.
+fn unicast_targets(args: &Args) -> Vec<SocketAddr> {
+    args.targets
+        .iter()
+        .map(|&(ip, port, _)| SocketAddr::new(ip, port.unwrap_or(args.port)))
+        .collect()
+}
+
+/// Creates the UDP sender for `unicast` plus broadcast targets, honouring
+/// `--no-broadcast` and `--target-cache`.
+fn udp_sender(args: &Args, unicast: &[SocketAddr]) -> std::io::Result<UdpSender> {
+    match &args.target_cache {
+        Some(path) => UdpSender::with_cached_targets(args.port, unicast, TargetCache::new(path)),
+        None => UdpSender::with_targets(args.port, unicast, !args.no_broadcast),
+    }
+}
+
+/// Prints the `--diagnose` support dump without opening a stream.
+///
+/// Device selection follows `--device` (or monitor, then the default device)
+/// but never prompts.
+fn print_diagnostics(args: &Args) {
+    let (host, default_device, devices) = describe_input_devices();
+    let names: Vec<String> = devices.iter().map(|d| d.name.clone()).collect();
+
+    let chain: Vec<DeviceSelector> = if args.devices.is_empty() {
+        vec![DeviceSelector::Monitor, DeviceSelector::Default]
+    } else {
+        args.devices.clone()
+    };
+    let selected = resolve_device_chain(&chain, &names, default_device.as_deref(), None, || None)
+        .map(|(_, name)| name);
+    let dsp = selected
+        .as_ref()
+        .and_then(|name| devices.iter().find(|d| &d.name == name)?.sample_rate)
+        .map(|rate| build_dsp(args, rate));
+
+    let targets = match &args.output {
+        Output::Udp => udp_sender(args, &unicast_targets(args))
+            .map(|s| s.targets().to_vec())
+            .unwrap_or_default(),
+        #[cfg(unix)]
+        Output::Unix(_) => Vec::new(),
+    };
+
+    print!(
+        "{}",
+        diagnostics::gather(host, devices, selected, targets, dsp.as_ref())
+    );
+}
+
+/// Prints a CSV summary (spectrum, tempo, loudness) of each WAV in `dir`.
+fn print_dir_analysis(dir: &std::path::Path) -> std::io::Result<()> {
+    println!("{}", csv_header());
+    for (path, summary) in analyze_dir(dir)? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match summary {
+            Ok(summary) => println!("{}", summary.to_csv_row(&name)),
+            Err(e) => eprintln!("Skipping {name}: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Renders `wav` through the default DSP and writes the packets to `out`.
+fn write_golden(wav: &std::path::Path, out: &std::path::Path) -> std::io::Result<()> {
+    let audio = read_wav(wav)?;
+    let packets = render_packets(&audio.samples, audio.sample_rate);
+    std::fs::write(out, packets.concat())?;
+    println!("Wrote {} packets to {}", packets.len(), out.display());
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use crate::packet::UdpSender;
+use std::io::{ErrorKind, Result};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// Largest datagram the relay reads; anything longer is not AudioSync.
+const MAX_DATAGRAM: usize = 128;
+
+/// What [`Bridge::relay_one`] did with a received datagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relayed {
+    /// A valid V2 packet was re-sent to the targets
+    Forwarded(SocketAddr),
+    /// The datagram was not a V2 packet and was dropped
+    Rejected(SocketAddr),
+    /// The datagram came from the bridge's own sender (a broadcast echo)
+    Echo,
+    /// No datagram arrived before the read timeout
+    Idle,
+}
+
+/// AudioSync relay: listens for V2 packets and re-sends each valid one
+/// unchanged to a list of targets, for installs spanning subnets that the
+/// analyzer's broadcasts don't reach.
+pub struct Bridge {
+    socket: UdpSocket,
+    sender: UdpSender,
+    own_port: u16, // Source port of our own sends, to drop broadcast echoes
+}
+
+impl Bridge {
+    /// Listens on `listen` and forwards through `sender`.
+    ///
+    /// `timeout` bounds each [`relay_one`](Self::relay_one) call so a caller
+    /// can poll a shutdown flag.
+    pub fn bind(listen: SocketAddr, sender: UdpSender, timeout: Duration) -> Result<Self> {
+        let socket = UdpSocket::bind(listen)?;
+        socket.set_read_timeout(Some(timeout))?;
+        let own_port = sender.local_addr()?.port();
+        Ok(Self {
+            socket,
+            sender,
+            own_port,
+        })
+    }
+
+    /// Address the bridge listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Targets packets are forwarded to.
+    pub fn targets(&self) -> &[SocketAddr] {
+        self.sender.targets()
+    }
+
+    /// Receives one datagram and forwards it if it is a valid V2 packet.
+    ///
+    /// # Returns
+    /// What happened to the datagram, or the socket error. A failure to
+    /// reach every target is also returned as an error.
+    pub fn relay_one(&mut self) -> Result<Relayed> {
+        let mut buf = [0u8; MAX_DATAGRAM];
+        let (len, src) = match self.socket.recv_from(&mut buf) {
+            Ok(v) => v,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(Relayed::Idle)
+            }
+            Err(e) => return Err(e),
+        };
+        if src.port() == self.own_port {
+            return Ok(Relayed::Echo);
+        }
+        match self.sender.forward(&buf[..len]) {
+            Ok(()) => Ok(Relayed::Forwarded(src)),
+            Err(e) if e.kind() == ErrorKind::InvalidData => Ok(Relayed::Rejected(src)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Directed broadcast address of an IPv4 subnet in CIDR notation, e.g.
+/// `192.168.2.0/24` -> `192.168.2.255`.
+pub fn subnet_broadcast(cidr: &str) -> std::result::Result<Ipv4Addr, String> {
+    let (addr, prefix) = cidr
+        .split_once('/')
+        .ok_or_else(|| format!("expected ADDR/PREFIX, got '{cidr}'"))?;
+    let addr: Ipv4Addr = addr
+        .parse()
+        .map_err(|_| format!("invalid IPv4 address '{addr}'"))?;
+    let prefix: u32 = prefix
+        .parse()
+        .ok()
+        .filter(|p| (1..=30).contains(p))
+        .ok_or_else(|| format!("invalid prefix length '{prefix}' (expected 1-30)"))?;
+    Ok(Ipv4Addr::from(u32::from(addr) | (u32::MAX >> prefix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::AudioSyncPacketV2;
+
+    fn listener() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        socket
+    }
+
+    #[test]
+    fn test_bridge_forwards_unchanged_to_all_targets() {
+        let wleds = [listener(), listener(), listener()];
+        let addrs: Vec<SocketAddr> = wleds.iter().map(|s| s.local_addr().unwrap()).collect();
+        let sender = UdpSender::with_targets(0, &addrs, false).unwrap();
+        let mut bridge = Bridge::bind(
+            "127.0.0.1:0".parse().unwrap(),
+            sender,
+            Duration::from_secs(1),
+        )
+        .unwrap();
+
+        let packet = AudioSyncPacketV2 {
+            sample_raw: 99.5,
+            sample_smth: 80.25,
+            sample_peak: 1,
+            fft_result: std::array::from_fn(|i| (i * 16) as u8),
+            zero_crossing_count: 123,
+            fft_magnitude: 4.5,
+            fft_major_peak: 523.25,
+        };
+        let mut bytes = packet.to_bytes(200);
+        bytes[6] = 0x12; // Pressure from the source must survive too
+
+        let analyzer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let bridge_addr = bridge.local_addr().unwrap();
+        analyzer.send_to(&bytes, bridge_addr).unwrap();
+        let src = analyzer.local_addr().unwrap();
+        assert_eq!(bridge.relay_one().unwrap(), Relayed::Forwarded(src));
+
+        let mut buf = [0u8; MAX_DATAGRAM];
+        for wled in &wleds {
+            let n = wled.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..n], &bytes[..]);
+        }
+
+        // Garbage is dropped, not forwarded
+        analyzer.send_to(b"hello", bridge_addr).unwrap();
+        assert_eq!(bridge.relay_one().unwrap(), Relayed::Rejected(src));
+        wleds[0]
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        assert!(wleds[0].recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_subnet_broadcast() {
+        assert_eq!(
+            subnet_broadcast("192.168.2.0/24").unwrap(),
+            Ipv4Addr::new(192, 168, 2, 255)
+        );
+        assert_eq!(
+            subnet_broadcast("10.1.0.7/16").unwrap(),
+            Ipv4Addr::new(10, 1, 255, 255)
+        );
+        assert!(subnet_broadcast("10.0.0.0").is_err());
+        assert!(subnet_broadcast("10.0.0.0/32").is_err());
+    }
+}
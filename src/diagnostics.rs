@@ -0,0 +1,209 @@
+use crate::dsp::DspProcessor;
+use std::fmt;
+use std::net::SocketAddr;
+
+/// One input device as seen by cpal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    pub name: String,
+    /// Default input config (e.g. "2ch 48000Hz F32"), or the error probing it
+    pub default_config: Result<String, String>,
+    /// Sample rate of the default input config
+    pub sample_rate: Option<u32>,
+    /// Supported config ranges
+    pub configs: Vec<String>,
+}
+
+/// Support dump printed by `--diagnose` for bug reports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub host: String,
+    pub devices: Vec<DeviceInfo>,
+    /// Device the server would capture from, if any resolves
+    pub selected_device: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// Addresses packets would be sent to (broadcast and `--target`)
+    pub targets: Vec<SocketAddr>,
+    /// Effective DSP settings as (name, value) pairs
+    pub dsp: Vec<(&'static str, String)>,
+}
+
+/// Bundles environment, device, network, and DSP information.
+///
+/// `dsp` and `targets` should be built with the same flags as a normal run,
+/// so the dump shows the effective configuration.
+pub fn gather(
+    host: String,
+    devices: Vec<DeviceInfo>,
+    selected_device: Option<String>,
+    targets: Vec<SocketAddr>,
+    dsp: Option<&DspProcessor>,
+) -> Diagnostics {
+    Diagnostics {
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        host,
+        devices,
+        selected_device,
+        sample_rate: dsp.map(DspProcessor::sample_rate),
+        targets,
+        dsp: dsp.map(dsp_settings).unwrap_or_default(),
+    }
+}
+
+fn dsp_settings(dsp: &DspProcessor) -> Vec<(&'static str, String)> {
+    let (agc_min, agc_max) = dsp.agc_range();
+    let config = dsp.config();
+    let advance = dsp.advance();
+    vec![
+        (
+            "FFT",
+            format!(
+                "{} points, {:?} window, advance {advance} samples ({:.0}% overlap)",
+                config.fft_size,
+                config.window,
+                100.0 * (1.0 - advance as f32 / config.fft_size as f32).max(0.0),
+            ),
+        ),
+        ("hop size", format!("{} samples", dsp.hop_size())),
+        (
+            "bins",
+            format!(
+                "{:.0}-{:.0} Hz, {:?} binning, {:?} scale",
+                config.freq_min, config.freq_max, config.binning, config.bin_scale
+            ),
+        ),
+        ("input gain", format!("{:+.1} dB", dsp.input_gain_db())),
+        ("master gain", format!("{:.2}", dsp.gain())),
+        ("output gain", format!("{:.2}", dsp.output_gain())),
+        (
+            "beat detection",
+            format!(
+                "{:?} on {:.0}-{:.0} Hz, history {} frames",
+                config.beat_mode, config.beat_freq_min, config.beat_freq_max, config.beat_history
+            ),
+        ),
+        ("beat threshold", format!("{:.2}", dsp.beat_threshold())),
+        (
+            "AGC",
+            match config.fixed_gain {
+                Some(scale) => format!("off, fixed gain {scale}"),
+                None if dsp.is_agc_frozen() => format!("frozen at {agc_min:.2}..{agc_max:.2}"),
+                None => format!(
+                    "adaptive, attack {:.2}, release {:.2}, span floor {}",
+                    config.agc_attack,
+                    config.agc_release,
+                    dsp.agc_span_floor()
+                ),
+            },
+        ),
+        ("bin floor", dsp.bin_floor().to_string()),
+        (
+            "silence threshold",
+            format!(
+                "{} (exit {})",
+                config.silence_threshold, config.silence_exit_threshold
+            ),
+        ),
+        ("level mode", format!("{:?}", config.level_mode)),
+        (
+            "smoothing",
+            format!(
+                "factor {:.2}, tau {:.0}ms",
+                dsp.smoothing_factor(),
+                dsp.smoothing_time_constant() * 1000.0
+            ),
+        ),
+    ]
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "wled-audio-server {}", self.version)?;
+        writeln!(f, "OS: {} ({})", self.os, self.arch)?;
+        writeln!(f, "Audio host: {}", self.host)?;
+
+        writeln!(f, "Input devices ({}):", self.devices.len())?;
+        for dev in &self.devices {
+            match &dev.default_config {
+                Ok(config) => writeln!(f, "  {} [default: {config}]", dev.name)?,
+                Err(e) => writeln!(f, "  {} [no default config: {e}]", dev.name)?,
+            }
+            for config in &dev.configs {
+                writeln!(f, "    {config}")?;
+            }
+        }
+
+        match &self.selected_device {
+            Some(name) => writeln!(f, "Selected device: {name}")?,
+            None => writeln!(f, "Selected device: <none>")?,
+        }
+        match self.sample_rate {
+            Some(rate) => writeln!(f, "Sample rate: {rate} Hz")?,
+            None => writeln!(f, "Sample rate: <unknown>")?,
+        }
+
+        let targets: Vec<String> = self.targets.iter().map(|t| t.to_string()).collect();
+        writeln!(f, "Network targets: {}", targets.join(", "))?;
+
+        writeln!(f, "DSP:")?;
+        for (name, value) in &self.dsp {
+            writeln!(f, "  {name}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather_populates_report() {
+        let devices = vec![
+            DeviceInfo {
+                name: "Monitor of Speakers".into(),
+                default_config: Ok("2ch 48000Hz F32".into()),
+                sample_rate: Some(48000),
+                configs: vec!["2ch 8000-96000Hz F32".into()],
+            },
+            DeviceInfo {
+                name: "Broken".into(),
+                default_config: Err("device busy".into()),
+                sample_rate: None,
+                configs: Vec::new(),
+            },
+        ];
+        let targets = vec![SocketAddr::from(([192, 168, 1, 255], 11988))];
+        let mut dsp = DspProcessor::new(44100);
+        dsp.set_input_gain_db(-6.0);
+
+        let diag = gather(
+            "ALSA".into(),
+            devices,
+            Some("Monitor of Speakers".into()),
+            targets,
+            Some(&dsp),
+        );
+
+        assert_eq!(diag.devices.len(), 2);
+        assert_eq!(diag.sample_rate, Some(44100));
+        assert!(!diag.os.is_empty());
+        assert!(diag
+            .dsp
+            .iter()
+            .any(|(k, v)| *k == "input gain" && v == "-6.0 dB"));
+        let report = diag.to_string();
+        assert!(report.contains("Audio host: ALSA"));
+        assert!(report.contains("Selected device: Monitor of Speakers"));
+        assert!(report.contains("Broken [no default config: device busy]"));
+        assert!(report.contains("Network targets: 192.168.1.255:11988"));
+        assert!(report.contains("FFT: 2048 points, FlatTop window, advance 1024 samples"));
+        assert!(report.contains("bins: 60-6000 Hz"));
+        assert!(report.contains("AGC: adaptive, attack 0.75"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Tiny arithmetic expressions for per-bin output transforms.
+//!
+//! A [`BinExpr`] is parsed once from a formula such as `x^1.5 * 1.2` and then
+//! evaluated for every bin of every frame, so power users can reshape the
+//! output curve without recompiling. Two variables are available: `x`, the
+//! bin's normalized level (1.0 = full scale, 255), and `i`, the bin index
+//! (0..16). Supported are numbers, `+ - * / ^` with the usual precedence
+//! (`^` binds tightest and is right-associative), unary minus, and
+//! parentheses.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Num(f32),
+    Level,
+    Index,
+    Neg(Box<Node>),
+    Add(Box<Node>, Box<Node>),
+    Sub(Box<Node>, Box<Node>),
+    Mul(Box<Node>, Box<Node>),
+    Div(Box<Node>, Box<Node>),
+    Pow(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, x: f32, i: f32) -> f32 {
+        match self {
+            Node::Num(n) => *n,
+            Node::Level => x,
+            Node::Index => i,
+            Node::Neg(a) => -a.eval(x, i),
+            Node::Add(a, b) => a.eval(x, i) + b.eval(x, i),
+            Node::Sub(a, b) => a.eval(x, i) - b.eval(x, i),
+            Node::Mul(a, b) => a.eval(x, i) * b.eval(x, i),
+            Node::Div(a, b) => a.eval(x, i) / b.eval(x, i),
+            Node::Pow(a, b) => a.eval(x, i).powf(b.eval(x, i)),
+        }
+    }
+}
+
+/// A parsed per-bin transform formula.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BinExpr {
+    source: String,
+    root: Node,
+}
+
+impl BinExpr {
+    /// Evaluates the formula for bin `index` at normalized level `x`.
+    pub fn eval(&self, x: f32, index: usize) -> f32 {
+        self.root.eval(x, index as f32)
+    }
+}
+
+/// Recursive-descent parser over the expression's bytes.
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<u8> {
+        while self.src.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+        self.src.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        loop {
+            if self.eat(b'+') {
+                node = Node::Add(Box::new(node), Box::new(self.product()?));
+            } else if self.eat(b'-') {
+                node = Node::Sub(Box::new(node), Box::new(self.product()?));
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    // product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        loop {
+            if self.eat(b'*') {
+                node = Node::Mul(Box::new(node), Box::new(self.unary()?));
+            } else if self.eat(b'/') {
+                node = Node::Div(Box::new(node), Box::new(self.unary()?));
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat(b'-') {
+            Ok(Node::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    // power := atom ('^' unary)?
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.eat(b'^') {
+            Ok(Node::Pow(Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // atom := number | 'x' | 'i' | '(' sum ')'
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let node = self.sum()?;
+                if !self.eat(b')') {
+                    return Err(format!("expected ')' at position {}", self.pos));
+                }
+                Ok(node)
+            }
+            Some(b'x') => {
+                self.pos += 1;
+                Ok(Node::Level)
+            }
+            Some(b'i') => {
+                self.pos += 1;
+                Ok(Node::Index)
+            }
+            Some(c) if c.is_ascii_digit() || c == b'.' => {
+                let start = self.pos;
+                while self
+                    .src
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == b'.')
+                {
+                    self.pos += 1;
+                }
+                let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+                text.parse()
+                    .map(Node::Num)
+                    .map_err(|_| format!("invalid number '{text}'"))
+            }
+            Some(c) => Err(format!(
+                "unexpected '{}' at position {}",
+                c as char, self.pos
+            )),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl FromStr for BinExpr {
+    type Err = String;
+
+    /// Parses a formula over `x` and `i`, e.g. `x^1.5 * 1.2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            src: s.as_bytes(),
+            pos: 0,
+        };
+        let root = parser.sum()?;
+        if parser.peek().is_some() {
+            return Err(format!(
+                "unexpected '{}' at position {}",
+                s[parser.pos..].chars().next().unwrap(),
+                parser.pos
+            ));
+        }
+        Ok(Self {
+            source: s.trim().to_string(),
+            root,
+        })
+    }
+}
+
+impl fmt::Display for BinExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(s: &str, x: f32, i: usize) -> f32 {
+        s.parse::<BinExpr>().unwrap().eval(x, i)
+    }
+
+    #[test]
+    fn test_bin_expr_evaluates_formulas() {
+        assert_eq!(eval("x", 0.25, 0), 0.25);
+        assert!((eval("x^1.5", 0.25, 0) - 0.125).abs() < 1e-6);
+        assert!((eval("x^1.5 * 2", 0.25, 0) - 0.25).abs() < 1e-6);
+        assert_eq!(eval("1 + 2 * 3", 0.0, 0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, 0), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0, 0), 512.0);
+        assert_eq!(eval("-2^2", 0.0, 0), -4.0);
+        assert_eq!(eval("x * (1 + i / 15)", 0.5, 15), 1.0);
+        assert_eq!(eval(" 10 - 4 - 3 ", 0.0, 0), 3.0);
+    }
+
+    #[test]
+    fn test_bin_expr_rejects_bad_input() {
+        for bad in ["", "x +", "(x", "x)", "y", "1..2", "x ** 2", "2 x"] {
+            assert!(bad.parse::<BinExpr>().is_err(), "'{bad}' parsed");
+        }
+        assert_eq!("x^1.5".parse::<BinExpr>().unwrap().to_string(), "x^1.5");
+    }
+}
@@ -0,0 +1,255 @@
+//! Integer-only binning, AGC, and beat detection for targets without an FPU.
+//!
+//! Enabled with the `fixed-point` cargo feature. The f32 [`DspProcessor`]
+//! remains the canonical implementation; this path trades a little precision
+//! for doing the per-frame stages entirely in integer arithmetic.
+//!
+//! Input is the positive half of the FFT magnitude spectrum in Q8 fixed point
+//! (magnitude × 256), as produced by an integer FFT. Levels are tracked in Q8
+//! internally. Against the f32 path on the same spectrum, `fft_result` stays
+//! within [`BIN_TOLERANCE`] and beats (`sample_peak`) match.
+//!
+//! [`DspProcessor`]: crate::dsp::DspProcessor
+
+use crate::dsp::{
+    bin_edges, DspConfig, AGC_ATTACK_NEW, AGC_RELEASE_NEW, BEAT_COOLDOWN, BEAT_FREQ_MAX,
+    BEAT_FREQ_MIN, BEAT_HISTORY, BEAT_THRESHOLD, BEAT_WARMUP_FRAMES, FFT_BIN_SCALE, FFT_SIZE,
+    HOP_SIZE, NUM_BINS,
+};
+
+/// Largest expected difference between a fixed-point `fft_result` bin and
+/// the f32 reference on the same spectrum.
+pub const BIN_TOLERANCE: u8 = 3;
+
+/// Fractional bits of the Q8 format used for magnitudes and levels.
+const FRAC_BITS: u32 = 8;
+const ONE: i64 = 1 << FRAC_BITS;
+
+/// `1 / FFT_BIN_SCALE` in Q10, applied to `sqrt(magnitude)` in Q8.
+const BIN_SCALE_Q10: i64 = (1024.0 / FFT_BIN_SCALE) as i64;
+
+/// AGC smoothing weights in Q8.
+const ATTACK_NEW: i64 = (AGC_ATTACK_NEW * ONE as f32 + 0.5) as i64;
+const ATTACK_OLD: i64 = ONE - ATTACK_NEW;
+const RELEASE_NEW: i64 = (AGC_RELEASE_NEW * ONE as f32 + 0.5) as i64;
+const RELEASE_OLD: i64 = ONE - RELEASE_NEW;
+
+/// Result of one fixed-point frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedFrame {
+    /// 16 normalized frequency bins (0-255)
+    pub fft_result: [u8; NUM_BINS],
+    /// 1 = beat detected
+    pub sample_peak: u8,
+}
+
+/// Fixed-point counterpart of the binning, AGC, and beat stages of
+/// [`DspProcessor`](crate::dsp::DspProcessor) with default settings.
+///
+/// Only non-silent frames should be passed in, matching the f32 path, which
+/// leaves AGC and beat state untouched on silence.
+pub struct FixedStages {
+    bin_edges: Vec<usize>,
+    agc_min: i64, // Q8
+    agc_max: i64, // Q8
+    beat_history: [u64; BEAT_HISTORY],
+    beat_idx: usize,
+    beat_frames: usize,
+    beat_freq_lo: usize,
+    beat_freq_hi: usize,
+    beat_threshold_q8: u64,
+    beat_cooldown_frames: usize, // Frames a beat blocks the next one for
+    frames_since_beat: usize,
+}
+
+impl FixedStages {
+    /// Creates the stages for the given sample rate.
+    ///
+    /// Bin edges are derived once here, so the per-frame path needs no
+    /// floating point.
+    pub fn new(sample_rate: u32) -> Self {
+        let freq_resolution = sample_rate as f32 / FFT_SIZE as f32;
+        Self {
+            bin_edges: bin_edges(freq_resolution, &DspConfig::default()),
+            agc_min: 0,
+            agc_max: ONE,
+            beat_history: [0; BEAT_HISTORY],
+            beat_idx: 0,
+            beat_frames: 0,
+            beat_freq_lo: (BEAT_FREQ_MIN / freq_resolution).round() as usize,
+            beat_freq_hi: (BEAT_FREQ_MAX / freq_resolution).round() as usize,
+            beat_threshold_q8: (BEAT_THRESHOLD * ONE as f32).round() as u64,
+            beat_cooldown_frames: (BEAT_COOLDOWN.as_secs_f32() * sample_rate as f32
+                / HOP_SIZE as f32)
+                .ceil() as usize,
+            frames_since_beat: usize::MAX,
+        }
+    }
+
+    /// Processes one frame of Q8 magnitudes (`FFT_SIZE / 2` values).
+    pub fn process(&mut self, magnitudes: &[u32]) -> FixedFrame {
+        let half = magnitudes.len().min(FFT_SIZE / 2);
+
+        // --- 16 log-spaced bins (Q8) ---
+        let mut raw_bins = [0i64; NUM_BINS];
+        for (i, raw_bin) in raw_bins.iter_mut().enumerate() {
+            let lo = self.bin_edges[i];
+            let hi = self.bin_edges[i + 1].max(lo + 1);
+            let bin_max = magnitudes[lo.min(half)..hi.min(half)]
+                .iter()
+                .copied()
+                .max()
+                .unwrap_or(0);
+            // sqrt of a Q16 value is Q8
+            let sqrt_q8 = ((bin_max as u64) << FRAC_BITS).isqrt() as i64;
+            *raw_bin = (sqrt_q8 * BIN_SCALE_Q10) >> 10;
+        }
+
+        // --- AGC ---
+        let frame_max = raw_bins.iter().copied().max().unwrap_or(0);
+        let frame_min = raw_bins.iter().copied().min().unwrap_or(0);
+        self.agc_max = Self::smooth_max(self.agc_max, frame_max);
+        self.agc_min = Self::smooth_min(self.agc_min, frame_min);
+
+        let span = (self.agc_max - self.agc_min).max(ONE);
+
+        // --- Normalize bins to 0..255 ---
+        let mut fft_result = [0u8; NUM_BINS];
+        for (out, &raw) in fft_result.iter_mut().zip(&raw_bins) {
+            *out = ((raw - self.agc_min) * 255 / span).clamp(0, 255) as u8;
+        }
+
+        // --- Beat detection (energy in Q16) ---
+        let beat_energy: u64 = magnitudes[self.beat_freq_lo.min(half)..self.beat_freq_hi.min(half)]
+            .iter()
+            .map(|&m| m as u64 * m as u64)
+            .sum();
+
+        if self.beat_frames == 0 {
+            self.beat_history.fill(beat_energy);
+        }
+        self.beat_frames = self.beat_frames.saturating_add(1);
+
+        self.beat_history[self.beat_idx] = beat_energy;
+        self.beat_idx = (self.beat_idx + 1) % BEAT_HISTORY;
+
+        // Compare energy * 50 * 256 against sum * threshold instead of
+        // dividing, which keeps full precision
+        let sum: u128 = self.beat_history.iter().map(|&e| e as u128).sum();
+        let scaled_energy = beat_energy as u128 * BEAT_HISTORY as u128 * ONE as u128;
+        let warmed_up = self.beat_frames > BEAT_WARMUP_FRAMES;
+        let cooled_down = self.frames_since_beat >= self.beat_cooldown_frames;
+        let sample_peak = u8::from(
+            warmed_up && cooled_down && scaled_energy > sum * self.beat_threshold_q8 as u128,
+        );
+        self.frames_since_beat = if sample_peak != 0 {
+            0
+        } else {
+            self.frames_since_beat.saturating_add(1)
+        };
+
+        FixedFrame {
+            fft_result,
+            sample_peak,
+        }
+    }
+
+    /// Asymmetric envelope follower for `agc_max`: fast rise, slow fall.
+    fn smooth_max(current: i64, target: i64) -> i64 {
+        if target > current {
+            (current * ATTACK_OLD + target * ATTACK_NEW) >> FRAC_BITS
+        } else {
+            (current * RELEASE_OLD + target * RELEASE_NEW) >> FRAC_BITS
+        }
+    }
+
+    /// Asymmetric envelope follower for `agc_min`: fast fall, slow rise.
+    fn smooth_min(current: i64, target: i64) -> i64 {
+        if target < current {
+            (current * ATTACK_OLD + target * ATTACK_NEW) >> FRAC_BITS
+        } else {
+            (current * RELEASE_OLD + target * RELEASE_NEW) >> FRAC_BITS
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::{flattop_window, DspProcessor};
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    const SR: u32 = 48000;
+
+    /// Q8 magnitudes of `frame`, computed the same way as the f32 path.
+    fn magnitudes_q8(frame: &[f32]) -> Vec<u32> {
+        let window = flattop_window(FFT_SIZE);
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let mut buf: Vec<Complex<f32>> = frame
+            .iter()
+            .zip(&window)
+            .map(|(&s, &w)| Complex::new(s * w, 0.0))
+            .collect();
+        fft.process(&mut buf);
+        buf[..FFT_SIZE / 2]
+            .iter()
+            .map(|c| (c.norm() * ONE as f32).round() as u32)
+            .collect()
+    }
+
+    /// Runs `samples` through both paths frame by frame (50% overlap).
+    fn compare(samples: &[f32]) -> usize {
+        let mut reference = DspProcessor::new(SR);
+        let mut fixed = FixedStages::new(SR);
+        let frames = reference.push_samples(samples);
+
+        for (n, frame) in frames.iter().enumerate() {
+            let start = n * FFT_SIZE / 2;
+            let got = fixed.process(&magnitudes_q8(&samples[start..start + FFT_SIZE]));
+            for (bin, (&g, &want)) in got.fft_result.iter().zip(&frame.fft_result).enumerate() {
+                assert!(
+                    g.abs_diff(want) <= BIN_TOLERANCE,
+                    "frame {n} bin {bin}: fixed {g} vs f32 {want}"
+                );
+            }
+            assert_eq!(got.sample_peak, frame.sample_peak, "frame {n} beat");
+        }
+        frames.len()
+    }
+
+    fn tone_mix(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SR as f32;
+                0.3 * (2.0 * std::f32::consts::PI * 220.0 * t).sin()
+                    + 0.1 * (2.0 * std::f32::consts::PI * 1760.0 * t).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed_matches_f32_on_tones() {
+        assert!(compare(&tone_mix(SR as usize)) > 40);
+    }
+
+    #[test]
+    fn test_fixed_matches_f32_beats() {
+        // Quiet tone with loud 80ms kicks every 0.5s
+        let mut samples = tone_mix(2 * SR as usize);
+        for (i, s) in samples.iter_mut().enumerate() {
+            let pos = i % (SR as usize / 2);
+            if i > SR as usize / 2 && pos < 3840 {
+                let t = pos as f32 / SR as f32;
+                *s += 0.8 * (2.0 * std::f32::consts::PI * 150.0 * t).sin();
+            }
+        }
+        compare(&samples);
+
+        let mut fixed = FixedStages::new(SR);
+        let beats: usize = samples
+            .chunks_exact(FFT_SIZE)
+            .map(|c| fixed.process(&magnitudes_q8(c)).sample_peak as usize)
+            .sum();
+        assert!(beats > 0, "kicks should register as beats");
+    }
+}
@@ -0,0 +1,237 @@
+/// Interpolation used when stretching the bins to a wider output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Straight lines between neighbouring bins
+    Linear,
+    /// Monotone cubic (Fritsch–Carlson): smooth, without overshooting the bins
+    Cubic,
+}
+
+/// Resamples `bins` to `len` values spanning the same range.
+///
+/// The first and last outputs equal the first and last bins, so a 16-bin
+/// spectrum can drive e.g. a 64-column matrix. The V2 sync packet always
+/// carries the original 16 bins; this is for wider local outputs.
+pub fn interpolate_bins(bins: &[u8], len: usize, mode: Interpolation) -> Vec<u8> {
+    if bins.is_empty() || len == 0 {
+        return Vec::new();
+    }
+    if bins.len() == 1 || len == 1 {
+        return vec![bins[0]; len];
+    }
+
+    let ys: Vec<f32> = bins.iter().map(|&b| b as f32).collect();
+    let tangents = match mode {
+        Interpolation::Linear => Vec::new(),
+        Interpolation::Cubic => monotone_tangents(&ys),
+    };
+
+    let last = ys.len() - 1;
+    (0..len)
+        .map(|j| {
+            let x = j as f32 * last as f32 / (len - 1) as f32;
+            let k = (x.floor() as usize).min(last - 1);
+            let t = x - k as f32;
+            let (y0, y1) = (ys[k], ys[k + 1]);
+            let y = match mode {
+                Interpolation::Linear => y0 + (y1 - y0) * t,
+                Interpolation::Cubic => {
+                    let (t2, t3) = (t * t, t * t * t);
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                        + (t3 - 2.0 * t2 + t) * tangents[k]
+                        + (-2.0 * t3 + 3.0 * t2) * y1
+                        + (t3 - t2) * tangents[k + 1]
+                }
+            };
+            y.round().clamp(0.0, 255.0) as u8
+        })
+        .collect()
+}
+
+/// Arranges `bins` symmetrically for center-out effects: bass in the middle,
+/// treble at both edges, e.g. `[b15..b0, b0..b15]` for a width of 32.
+///
+/// Each half is `bins` resampled (linearly) to half the width; for an odd
+/// `width` the bass value appears once, in the center.
+pub fn mirror_bins(bins: &[u8], width: usize) -> Vec<u8> {
+    let half = interpolate_bins(bins, width.div_ceil(2), Interpolation::Linear);
+    let mut out: Vec<u8> = half.iter().rev().copied().collect();
+    let skip = width % 2; // Don't duplicate the center for odd widths
+    out.extend(half.iter().skip(skip));
+    out
+}
+
+/// How the 16 bins are laid out along a physical LED strip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedMapping {
+    /// Bass at the first LED, treble at the last
+    Stretch,
+    /// Bass at both ends, treble in the middle
+    Mirror,
+    /// Bass in the middle, treble at both ends (center-out)
+    BassCentered,
+}
+
+/// Maps `bins` onto `led_count` RGB colors for a realtime strip output.
+///
+/// Each LED gets a spectrum position per `mapping`; its level is the bins
+/// interpolated linearly at that position, and its color runs from red
+/// (bass) through green to blue (treble), scaled by the level.
+pub fn map_to_leds(bins: &[u8], led_count: usize, mapping: LedMapping) -> Vec<[u8; 3]> {
+    if bins.is_empty() {
+        return vec![[0; 3]; led_count];
+    }
+    let last = (bins.len() - 1) as f32;
+
+    (0..led_count)
+        .map(|j| {
+            // Position along the LED strip (0..=1) and in the spectrum (0..=1)
+            let along = if led_count > 1 {
+                j as f32 / (led_count - 1) as f32
+            } else {
+                0.0
+            };
+            let x = match mapping {
+                LedMapping::Stretch => along,
+                LedMapping::Mirror => 1.0 - (2.0 * along - 1.0).abs(),
+                LedMapping::BassCentered => (2.0 * along - 1.0).abs(),
+            };
+
+            let pos = x * last;
+            let k = (pos.floor() as usize).min(bins.len().saturating_sub(2));
+            let t = pos - k as f32;
+            let level = match bins.get(k + 1) {
+                Some(&next) => bins[k] as f32 + (next as f32 - bins[k] as f32) * t,
+                None => bins[k] as f32,
+            } / 255.0;
+
+            let rgb = [
+                (1.0 - 2.0 * x).max(0.0),
+                1.0 - (2.0 * x - 1.0).abs(),
+                (2.0 * x - 1.0).max(0.0),
+            ];
+            rgb.map(|c| (c * level * 255.0).round() as u8)
+        })
+        .collect()
+}
+
+/// Fritsch–Carlson tangents for unit-spaced points, limited so the Hermite
+/// curve never overshoots between neighbouring points.
+fn monotone_tangents(ys: &[f32]) -> Vec<f32> {
+    let n = ys.len();
+    let deltas: Vec<f32> = ys.windows(2).map(|w| w[1] - w[0]).collect();
+
+    let mut m = vec![0.0f32; n];
+    m[0] = deltas[0];
+    m[n - 1] = deltas[n - 2];
+    for k in 1..n - 1 {
+        if deltas[k - 1] * deltas[k] > 0.0 {
+            m[k] = (deltas[k - 1] + deltas[k]) / 2.0;
+        }
+    }
+
+    for (k, &d) in deltas.iter().enumerate() {
+        if d == 0.0 {
+            m[k] = 0.0;
+            m[k + 1] = 0.0;
+            continue;
+        }
+        let a = m[k] / d;
+        let b = m[k + 1] / d;
+        let norm = a * a + b * b;
+        if norm > 9.0 {
+            let tau = 3.0 / norm.sqrt();
+            m[k] = tau * a * d;
+            m[k + 1] = tau * b * d;
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_16_to_64_monotonic_and_boundary_preserving() {
+        let bins: [u8; 16] = [
+            0, 5, 10, 30, 31, 60, 90, 90, 120, 160, 170, 200, 230, 240, 250, 255,
+        ];
+        for mode in [Interpolation::Linear, Interpolation::Cubic] {
+            let out = interpolate_bins(&bins, 64, mode);
+            assert_eq!(out.len(), 64);
+            assert_eq!(out[0], bins[0], "{mode:?} first value");
+            assert_eq!(out[63], bins[15], "{mode:?} last value");
+            assert!(
+                out.windows(2).all(|w| w[0] <= w[1]),
+                "{mode:?} output should be monotonic: {out:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_interpolate_degenerate_lengths() {
+        assert!(interpolate_bins(&[1, 2, 3], 0, Interpolation::Linear).is_empty());
+        assert_eq!(interpolate_bins(&[7, 9], 1, Interpolation::Cubic), vec![7]);
+        assert_eq!(
+            interpolate_bins(&[10, 20], 3, Interpolation::Linear),
+            vec![10, 15, 20]
+        );
+    }
+
+    #[test]
+    fn test_mirror_bins_symmetric_sequence() {
+        let bins: Vec<u8> = (0..16).map(|i| i * 10).collect();
+
+        let mirrored = mirror_bins(&bins, 32);
+        let expected: Vec<u8> = bins.iter().rev().chain(bins.iter()).copied().collect();
+        assert_eq!(mirrored, expected);
+
+        let narrow = mirror_bins(&bins, 16);
+        assert_eq!(narrow.len(), 16);
+        assert_eq!(narrow[7], bins[0], "bass at the center");
+        assert_eq!(narrow[0], bins[15], "treble at the edges");
+        assert!(narrow.iter().eq(narrow.iter().rev()), "{narrow:?}");
+
+        let odd = mirror_bins(&bins, 31);
+        assert_eq!(odd.len(), 31);
+        assert_eq!(odd[15], bins[0]);
+        assert!(odd.iter().eq(odd.iter().rev()));
+    }
+
+    #[test]
+    fn test_map_to_leds_stretch_is_smooth() {
+        let bins = [200u8; 16];
+        let leds = map_to_leds(&bins, 30, LedMapping::Stretch);
+        assert_eq!(leds.len(), 30);
+
+        // Bass end is red, treble end is blue
+        assert_eq!(leds[0], [200, 0, 0]);
+        assert_eq!(leds[29], [0, 0, 200]);
+        // No channel jumps by more than a small step between neighbours
+        for (j, w) in leds.windows(2).enumerate() {
+            for c in 0..3 {
+                assert!(
+                    w[0][c].abs_diff(w[1][c]) <= 30,
+                    "LED {j}->{}: {:?} -> {:?}",
+                    j + 1,
+                    w[0],
+                    w[1]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_map_to_leds_symmetric_mappings() {
+        let bins: Vec<u8> = (0..16).map(|i| 255 - i * 16).collect();
+        for mapping in [LedMapping::Mirror, LedMapping::BassCentered] {
+            let leds = map_to_leds(&bins, 31, mapping);
+            assert!(leds.iter().eq(leds.iter().rev()), "{mapping:?} symmetric");
+        }
+        // Bass (loudest, red) sits in the middle for center-out
+        let centered = map_to_leds(&bins, 31, LedMapping::BassCentered);
+        assert_eq!(centered[15], [255, 0, 0]);
+        assert_eq!(map_to_leds(&bins, 31, LedMapping::Mirror)[0], [255, 0, 0]);
+    }
+}
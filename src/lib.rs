@@ -1,3 +1,21 @@
+pub mod analysis;
 pub mod audio;
+pub mod bridge;
+pub mod diagnostics;
 pub mod dsp;
+pub mod expr;
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
+pub mod layout;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
 pub mod packet;
+#[cfg(feature = "http")]
+pub mod preset;
+pub mod profile;
+pub mod recorder;
+pub mod replay;
+pub mod resample;
+pub mod schedule;
+pub mod source;
+pub mod wav;
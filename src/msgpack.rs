@@ -0,0 +1,277 @@
+//! MessagePack per-frame output for third-party consumers.
+//!
+//! Enabled with the `msgpack` cargo feature. Each [`DspFrame`] is encoded as a
+//! MessagePack map keyed by field name, so consumers can decode it without
+//! knowing the struct layout and new fields don't break existing readers,
+//! while numbers and bin arrays stay in compact binary form.
+//!
+//! Over UDP every datagram carries one frame. Over TCP frames are written back
+//! to back with no extra framing; MessagePack values are self-delimiting, so
+//! a reader decodes them one after another from the stream. The TCP socket
+//! is non-blocking so a slow consumer can't stall the audio loop: bytes it
+//! hasn't taken yet are queued, and once the queue is full whole frames are
+//! dropped until it catches up.
+
+use crate::dsp::DspFrame;
+use std::io::{self, ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+
+/// Destination for the MessagePack sink, as given to `--msgpack`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsgpackTarget {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+}
+
+/// Parses `udp:HOST:PORT` or `tcp:HOST:PORT`.
+pub fn parse_msgpack_target(s: &str) -> Result<MsgpackTarget, String> {
+    let (scheme, addr) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected udp:HOST:PORT or tcp:HOST:PORT, got '{s}'"))?;
+    let addr = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("invalid address '{addr}': {e}"))?
+        .next()
+        .ok_or_else(|| format!("'{addr}' did not resolve to an address"))?;
+    match scheme {
+        "udp" => Ok(MsgpackTarget::Udp(addr)),
+        "tcp" => Ok(MsgpackTarget::Tcp(addr)),
+        _ => Err(format!(
+            "unsupported transport '{scheme}' (expected 'udp' or 'tcp')"
+        )),
+    }
+}
+
+/// Encodes `frame` as a MessagePack map with named fields.
+pub fn encode_frame(frame: &DspFrame) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(frame)
+}
+
+/// Decodes one frame produced by [`encode_frame`].
+pub fn decode_frame(bytes: &[u8]) -> Result<DspFrame, rmp_serde::decode::Error> {
+    rmp_serde::from_slice(bytes)
+}
+
+/// Most bytes queued for a TCP consumer that isn't keeping up, about a
+/// second of frames.
+const TCP_BACKLOG_MAX: usize = 64 * 1024;
+
+enum Transport {
+    Udp(UdpSocket),
+    Tcp {
+        stream: TcpStream,
+        backlog: Vec<u8>, // Encoded bytes the socket hasn't accepted yet
+    },
+}
+
+/// Sends every [`DspFrame`] as a MessagePack message over UDP or TCP.
+pub struct MsgpackSink {
+    transport: Transport,
+    dropped: u64, // Frames skipped because the TCP backlog was full
+}
+
+impl MsgpackSink {
+    /// Opens a sink for `target`, connecting first when it is TCP.
+    ///
+    /// # Returns
+    /// * `Ok(MsgpackSink)` - Ready to send
+    /// * `Err(io::Error)` - If the socket can't be bound or the TCP connect fails
+    pub fn connect(target: MsgpackTarget) -> io::Result<Self> {
+        let transport = match target {
+            MsgpackTarget::Udp(addr) => {
+                let bind: SocketAddr = if addr.is_ipv4() {
+                    "0.0.0.0:0".parse().unwrap()
+                } else {
+                    "[::]:0".parse().unwrap()
+                };
+                let socket = UdpSocket::bind(bind)?;
+                socket.connect(addr)?;
+                Transport::Udp(socket)
+            }
+            MsgpackTarget::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                stream.set_nonblocking(true)?;
+                Transport::Tcp {
+                    stream,
+                    backlog: Vec::new(),
+                }
+            }
+        };
+        Ok(Self {
+            transport,
+            dropped: 0,
+        })
+    }
+
+    /// Encodes and transmits one frame.
+    ///
+    /// Never blocks: over TCP, a frame that doesn't fit in the backlog is
+    /// dropped (see [`dropped_frames`](Self::dropped_frames)) rather than
+    /// reported as an error.
+    pub fn send(&mut self, frame: &DspFrame) -> io::Result<()> {
+        let bytes = encode_frame(frame).map_err(io::Error::other)?;
+        match &mut self.transport {
+            Transport::Udp(socket) => socket.send(&bytes).map(|_| ()),
+            Transport::Tcp { stream, backlog } => {
+                flush_backlog(stream, backlog)?;
+                // Frames are only dropped whole, so the stream stays decodable
+                if backlog.len() + bytes.len() > TCP_BACKLOG_MAX {
+                    self.dropped += 1;
+                    return Ok(());
+                }
+                backlog.extend_from_slice(&bytes);
+                flush_backlog(stream, backlog)
+            }
+        }
+    }
+
+    /// Frames dropped so far because a TCP consumer fell behind.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Writes as much of `backlog` as `stream` accepts without blocking and
+/// removes it from the queue.
+fn flush_backlog(stream: &mut TcpStream, backlog: &mut Vec<u8>) -> io::Result<()> {
+    let mut written = 0;
+    let result = loop {
+        if written == backlog.len() {
+            break Ok(());
+        }
+        match stream.write(&backlog[written..]) {
+            Ok(0) => break Err(ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+    backlog.drain(..written);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn full_frame() -> DspFrame {
+        let mut fft_result = [0u8; 16];
+        let mut peak_hold = [0u8; 16];
+        for i in 0..16 {
+            fft_result[i] = (i * 16) as u8;
+            peak_hold[i] = 255 - i as u8;
+        }
+        DspFrame {
+            sample_raw: 123.5,
+            sample_smth: 98.25,
+            sample_peak: 1,
+            fft_result,
+            zero_crossing_count: 321,
+            fft_magnitude: 4567.75,
+            fft_major_peak: 440.0,
+            peak_hold,
+            fft_magnitude_norm: Some(200),
+            estimated_bpm: 128.0,
+            spectral_centroid: 2500.0,
+            fft_result_right: Some([7; 16]),
+        }
+    }
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let frame = full_frame();
+        let bytes = encode_frame(&frame).unwrap();
+        assert_eq!(decode_frame(&bytes).unwrap(), frame);
+
+        let none = DspFrame {
+            fft_magnitude_norm: None,
+            ..full_frame()
+        };
+        assert_eq!(decode_frame(&encode_frame(&none).unwrap()).unwrap(), none);
+    }
+
+    #[test]
+    fn test_msgpack_is_keyed_by_field_name() {
+        let bytes = encode_frame(&full_frame()).unwrap();
+        let has = |key: &str| bytes.windows(key.len()).any(|w| w == key.as_bytes());
+        assert!(has("fft_result"));
+        assert!(has("fft_major_peak"));
+    }
+
+    #[test]
+    fn test_parse_msgpack_target() {
+        assert_eq!(
+            parse_msgpack_target("udp:127.0.0.1:9000"),
+            Ok(MsgpackTarget::Udp("127.0.0.1:9000".parse().unwrap()))
+        );
+        assert_eq!(
+            parse_msgpack_target("tcp:127.0.0.1:9001"),
+            Ok(MsgpackTarget::Tcp("127.0.0.1:9001".parse().unwrap()))
+        );
+        assert!(parse_msgpack_target("http:127.0.0.1:80").is_err());
+        assert!(parse_msgpack_target("udp:nope").is_err());
+    }
+
+    #[test]
+    fn test_msgpack_sink_udp() {
+        let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        rx.set_read_timeout(Some(std::time::Duration::from_secs(2)))
+            .unwrap();
+        let mut sink = MsgpackSink::connect(MsgpackTarget::Udp(rx.local_addr().unwrap())).unwrap();
+        sink.send(&full_frame()).unwrap();
+
+        let mut buf = [0u8; 1024];
+        let n = rx.recv(&mut buf).unwrap();
+        assert_eq!(decode_frame(&buf[..n]).unwrap(), full_frame());
+    }
+
+    #[test]
+    fn test_msgpack_sink_tcp_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sink =
+            MsgpackSink::connect(MsgpackTarget::Tcp(listener.local_addr().unwrap())).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let second = DspFrame {
+            sample_peak: 0,
+            ..full_frame()
+        };
+        sink.send(&full_frame()).unwrap();
+        sink.send(&second).unwrap();
+        drop(sink);
+
+        let mut reader = io::BufReader::new(stream);
+        let a: DspFrame = rmp_serde::from_read(&mut reader).unwrap();
+        let b: DspFrame = rmp_serde::from_read(&mut reader).unwrap();
+        assert_eq!(a, full_frame());
+        assert_eq!(b, second);
+    }
+
+    #[test]
+    fn test_msgpack_sink_tcp_never_blocks_on_a_stalled_reader() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sink =
+            MsgpackSink::connect(MsgpackTarget::Tcp(listener.local_addr().unwrap())).unwrap();
+        // Accepted but never read, so the socket buffers fill up
+        let (stream, _) = listener.accept().unwrap();
+
+        let start = std::time::Instant::now();
+        for _ in 0..100_000 {
+            sink.send(&full_frame()).unwrap();
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert!(sink.dropped_frames() > 0);
+
+        // Whatever was delivered is still a clean sequence of whole frames
+        let sent = 100_000 - sink.dropped_frames();
+        drop(sink);
+        let mut reader = io::BufReader::new(stream);
+        for _ in 0..sent.min(10) {
+            let frame: DspFrame = rmp_serde::from_read(&mut reader).unwrap();
+            assert_eq!(frame, full_frame());
+        }
+    }
+}
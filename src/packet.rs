@@ -1,7 +1,13 @@
+use crate::dsp::DspFrame;
 use if_addrs::{get_if_addrs, IfAddr};
 use std::collections::HashSet;
-use std::io::{Error, Result};
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
 /// V2 AudioSync packet for WLED AudioReactive (44 bytes, little-endian).
 ///
@@ -81,6 +87,292 @@ impl AudioSyncPacketV2 {
 
         buf
     }
+
+    /// Parses a 44-byte V2 packet, as produced by [`to_bytes`](Self::to_bytes).
+    ///
+    /// # Returns
+    /// The packet and its frame counter, or `None` if `bytes` has the wrong
+    /// length or header. The pressure field is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Self, u8)> {
+        let buf: &[u8; 44] = bytes.try_into().ok()?;
+        if &buf[..6] != b"00002\0" {
+            return None;
+        }
+        let f32_at = |i: usize| f32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let packet = Self {
+            sample_raw: f32_at(8),
+            sample_smth: f32_at(12),
+            sample_peak: buf[16],
+            fft_result: buf[18..34].try_into().ok()?,
+            zero_crossing_count: u16::from_le_bytes([buf[34], buf[35]]),
+            fft_magnitude: f32_at(36),
+            fft_major_peak: f32_at(40),
+        };
+        Some((packet, buf[17]))
+    }
+
+    /// Serializes the packet in the legacy V1 format (88 bytes) understood
+    /// by older AudioReactive builds.
+    ///
+    /// Mirrors WLED's `audioSyncPacket_v1` struct as laid out on the ESP32,
+    /// including its alignment padding:
+    ///
+    /// ```text
+    /// Offset  Size  Type      Field
+    /// 0       6     [u8;6]    header = "00001\0"
+    /// 6       32    [u8;32]   myVals (sample history, left zero)
+    /// 40      4     i32       sampleAgc (sampleSmth)
+    /// 44      4     i32       sampleRaw
+    /// 48      4     f32       sampleAvg (sampleSmth)
+    /// 52      1     u8        samplePeak
+    /// 53      16    [u8;16]   fftResult
+    /// 72      8     f64       FFT_Magnitude
+    /// 80      8     f64       FFT_MajorPeak
+    /// ```
+    ///
+    /// V1 has no frame counter or zero-crossing count.
+    pub fn to_bytes_v1(&self) -> [u8; 88] {
+        let mut buf = [0u8; 88];
+        buf[..6].copy_from_slice(b"00001\0");
+        buf[40..44].copy_from_slice(&(self.sample_smth as i32).to_le_bytes());
+        buf[44..48].copy_from_slice(&(self.sample_raw as i32).to_le_bytes());
+        buf[48..52].copy_from_slice(&self.sample_smth.to_le_bytes());
+        buf[52] = u8::from(self.sample_peak != 0);
+        buf[53..69].copy_from_slice(&self.fft_result);
+        buf[72..80].copy_from_slice(&f64::from(self.fft_magnitude).to_le_bytes());
+        buf[80..88].copy_from_slice(&f64::from(self.fft_major_peak).to_le_bytes());
+        buf
+    }
+}
+
+/// AudioSync packet format version a target expects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncVersion {
+    /// Legacy 88-byte format (AudioReactive before 0.14)
+    V1,
+    /// Current 44-byte format
+    #[default]
+    V2,
+}
+
+impl From<&DspFrame> for AudioSyncPacketV2 {
+    fn from(frame: &DspFrame) -> Self {
+        Self {
+            sample_raw: frame.sample_raw,
+            sample_smth: frame.sample_smth,
+            sample_peak: frame.sample_peak,
+            fft_result: frame.fft_result,
+            zero_crossing_count: frame.zero_crossing_count,
+            fft_magnitude: frame.fft_magnitude,
+            fft_major_peak: frame.fft_major_peak,
+        }
+    }
+}
+
+/// What beat-gated output sends for frames without a detected beat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BeatIdle {
+    /// Send nothing between beats
+    Suppress,
+    /// Send an all-zero packet between beats (LEDs go dark)
+    Zero,
+}
+
+/// Beat-gated packet selection for strobe-on-beat setups.
+///
+/// Frames with a detected beat (`sample_peak != 0`) pass through as full
+/// packets; other frames become an idle packet or are dropped per `idle`.
+///
+/// # Returns
+/// `Some(packet)` to transmit, or `None` if the frame should be skipped.
+pub fn beat_gate(frame: &DspFrame, idle: BeatIdle) -> Option<AudioSyncPacketV2> {
+    if frame.sample_peak != 0 {
+        return Some(AudioSyncPacketV2::from(frame));
+    }
+    match idle {
+        BeatIdle::Suppress => None,
+        BeatIdle::Zero => Some(AudioSyncPacketV2 {
+            sample_raw: 0.0,
+            sample_smth: 0.0,
+            sample_peak: 0,
+            fft_result: [0; 16],
+            zero_crossing_count: 0,
+            fft_magnitude: 0.0,
+            fft_major_peak: 0.0,
+        }),
+    }
+}
+
+/// Parses a comma-separated list of exactly 16 bin values (0-255) for
+/// `--static-bins`.
+pub fn parse_static_bins(s: &str) -> std::result::Result<[u8; 16], String> {
+    let values = s
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<u8>()
+                .map_err(|_| format!("invalid bin value '{}' (expected 0-255)", v.trim()))
+        })
+        .collect::<std::result::Result<Vec<u8>, String>>()?;
+    values
+        .try_into()
+        .map_err(|v: Vec<u8>| format!("expected 16 comma-separated bins, got {}", v.len()))
+}
+
+/// Parses `IP` or `IP:PORT`, optionally suffixed with `@v1`/`@v2` to pick
+/// the packet format; a missing port is left for the caller to default.
+pub fn parse_target(s: &str) -> std::result::Result<(IpAddr, Option<u16>, SyncVersion), String> {
+    let (addr, version) = match s.rsplit_once('@') {
+        Some((addr, "v1")) => (addr, SyncVersion::V1),
+        Some((addr, "v2")) => (addr, SyncVersion::V2),
+        Some((_, v)) => {
+            return Err(format!(
+                "unknown protocol version '{v}' (expected v1 or v2)"
+            ))
+        }
+        None => (s, SyncVersion::V2),
+    };
+    if let Ok(sock) = addr.parse::<SocketAddr>() {
+        return Ok((sock.ip(), Some(sock.port()), version));
+    }
+    addr.parse::<IpAddr>()
+        .map(|ip| (ip, None, version))
+        .map_err(|_| format!("invalid target '{s}' (expected IP or IP:PORT, optionally @v1)"))
+}
+
+/// Builds a packet carrying a fixed spectrum, for checking effect mappings
+/// without audio.
+///
+/// `sampleRaw`/`sampleSmth` are set to the mean bin level so volume-driven
+/// effects light up too; no beat is reported.
+pub fn static_packet(bins: &[u8; 16]) -> AudioSyncPacketV2 {
+    let level = bins.iter().map(|&b| f32::from(b)).sum::<f32>() / 16.0;
+    AudioSyncPacketV2 {
+        sample_raw: level,
+        sample_smth: level,
+        sample_peak: 0,
+        fft_result: *bins,
+        zero_crossing_count: 0,
+        fft_magnitude: 0.0,
+        fft_major_peak: 0.0,
+    }
+}
+
+/// WLED network protocols, each with its own listening port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WledProtocol {
+    /// AudioReactive AudioSync (UDP)
+    AudioSync,
+    /// Realtime UDP (DRGB/DNRGB, etc.)
+    Realtime,
+    /// JSON API (HTTP)
+    Json,
+}
+
+impl WledProtocol {
+    const ALL: [WledProtocol; 3] = [
+        WledProtocol::AudioSync,
+        WledProtocol::Realtime,
+        WledProtocol::Json,
+    ];
+
+    /// Port WLED listens on for this protocol by default.
+    pub fn default_port(self) -> u16 {
+        match self {
+            WledProtocol::AudioSync => 11988,
+            WledProtocol::Realtime => 21324,
+            WledProtocol::Json => 80,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WledProtocol::AudioSync => "AudioSync",
+            WledProtocol::Realtime => "realtime",
+            WledProtocol::Json => "JSON",
+        }
+    }
+}
+
+/// Checks that `port` is plausible for sending `protocol`.
+///
+/// Custom ports are fine; this only catches the easy mistake of using
+/// another WLED protocol's port (e.g. AudioSync to port 80), or port 0.
+///
+/// # Returns
+/// A warning message, or `None` if the port looks right.
+pub fn check_port(protocol: WledProtocol, port: u16) -> Option<String> {
+    if port == 0 {
+        return Some(format!("port 0 is not a valid {} port", protocol.name()));
+    }
+    WledProtocol::ALL
+        .into_iter()
+        .find(|&other| other != protocol && other.default_port() == port)
+        .map(|other| {
+            format!(
+                "port {port} is WLED's {} port; {} is normally sent to port {}",
+                other.name(),
+                protocol.name(),
+                protocol.default_port()
+            )
+        })
+}
+
+/// Wildcard address with an ephemeral port, in the IPv6 or IPv4 family.
+fn any_addr(ipv6: bool) -> SocketAddr {
+    if ipv6 {
+        "[::]:0".parse().unwrap()
+    } else {
+        "0.0.0.0:0".parse().unwrap()
+    }
+}
+
+/// How long [`probe_udp`] waits for an ICMP error after its probe datagram.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Probes whether `addr` appears to accept UDP packets.
+///
+/// Connects a UDP socket (failing fast if there is no route) and sends an
+/// empty datagram, which WLED ignores. If the host answers with ICMP
+/// port-unreachable the following receive fails with `ConnectionRefused`;
+/// silence until the timeout counts as reachable, since UDP gives no
+/// positive acknowledgement.
+pub fn probe_udp(addr: SocketAddr) -> Result<()> {
+    let socket = UdpSocket::bind(any_addr(addr.is_ipv6()))?;
+    socket.connect(addr)?;
+    socket.send(&[])?;
+    socket.set_read_timeout(Some(PROBE_TIMEOUT))?;
+    match socket.recv(&mut [0u8; 64]) {
+        Ok(_) => Ok(()),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Runs `probe` against each target for the `--check-targets` preflight.
+///
+/// # Returns
+/// Each target with `Ok(())` if it appears reachable, or the probe's error.
+pub fn check_targets<F>(targets: &[SocketAddr], mut probe: F) -> Vec<(SocketAddr, Result<()>)>
+where
+    F: FnMut(SocketAddr) -> Result<()>,
+{
+    targets.iter().map(|&addr| (addr, probe(addr))).collect()
+}
+
+/// Destination for AudioSync packets.
+///
+/// Implemented by every output transport so the main loop can stream frames
+/// without caring where they end up.
+pub trait FrameSink {
+    /// Serializes and transmits one packet, advancing the sink's frame counter.
+    fn send(&mut self, packet: &AudioSyncPacketV2) -> Result<()>;
+
+    /// Packets dropped because the transport's send buffer was full, which
+    /// are not reported as send errors. Zero for sinks that never drop.
+    fn would_block_drops(&self) -> u64 {
+        0
+    }
 }
 
 /// UDP packet sender with automatic frame counter management.
@@ -90,7 +382,114 @@ impl AudioSyncPacketV2 {
 pub struct UdpSender {
     socket: UdpSocket,
     targets: Vec<SocketAddr>,
+    versions: Vec<SyncVersion>, // Packet format per entry in `targets`
+    unicast: Vec<SocketAddr>,   // Explicit targets, kept across discovery refreshes
+    connected: bool,            // Socket is connect()ed to the single unicast target
     frame_counter: u8,
+    rate_limit: Option<TokenBucket>,
+    rate_limited: u64, // Packets skipped by the rate limiter
+    would_block: u64,  // Sends dropped because the socket buffer was full
+    discovery: Option<Receiver<Vec<SocketAddr>>>, // Pending background re-discovery
+}
+
+/// Last-known broadcast targets persisted between runs (`--target-cache`).
+///
+/// A plain text file with one `IP:PORT` per line; blank lines and `#`
+/// comments are ignored.
+pub struct TargetCache {
+    path: PathBuf,
+}
+
+impl TargetCache {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Reads the cached targets.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<SocketAddr>)` - The cached targets (possibly empty)
+    /// * `Err(io::Error)` - If the file can't be read, or `InvalidData` if
+    ///   any line is not an address
+    pub fn load(&self) -> Result<Vec<SocketAddr>> {
+        std::fs::read_to_string(&self.path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse().map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid target '{line}' in {}", self.path.display()),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces the cached targets, writing to a temporary file first so an
+    /// interrupted write never leaves a truncated cache behind.
+    pub fn store(&self, targets: &[SocketAddr]) -> Result<()> {
+        let mut contents = String::from("# wled-audio-server discovered targets\n");
+        for addr in targets {
+            contents.push_str(&format!("{addr}\n"));
+        }
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, contents)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Token-bucket rate limiter.
+///
+/// Tokens refill continuously at `rate` per second up to `burst`; each
+/// packet spends one. The long-run rate is capped at `rate` while up to
+/// `burst` packets may go out back to back after an idle period.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket. `rate` is in tokens per second; `burst` is
+    /// clamped to at least 1 so a single packet can always pass eventually.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: rate.max(0.0),
+            burst,
+            tokens: burst,
+            last: None,
+        }
+    }
+
+    /// Takes one token if available at `now`.
+    ///
+    /// # Returns
+    /// `true` if the caller may send, `false` if the bucket is empty.
+    pub fn try_acquire_at(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        }
+        self.last = Some(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// [`try_acquire_at`](Self::try_acquire_at) using the current time.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
 }
 
 impl UdpSender {
@@ -103,20 +502,247 @@ impl UdpSender {
     /// * `Ok(UdpSender)` - Ready-to-use sender with frame counter initialized to 0
     /// * `Err(io::Error)` - If socket setup fails
     pub fn new(port: u16) -> Result<Self> {
-        let socket = UdpSocket::bind("0.0.0.0:0")?;
-        socket.set_broadcast(true)?;
-        let targets = discover_broadcast_targets(port);
+        Self::with_targets(port, &[], true)
+    }
+
+    /// Creates a sender for explicit unicast targets, optionally combined with
+    /// broadcast discovery.
+    ///
+    /// Useful in mixed networks where some WLED devices are only reachable by
+    /// unicast (e.g. routed via a gateway) while others receive broadcasts.
+    ///
+    /// # Arguments
+    /// * `port` - Port for discovered broadcast targets
+    /// * `unicast` - Extra target addresses, each with its own port
+    /// * `broadcast` - Whether to also send to discovered broadcast addresses
+    ///
+    /// With exactly one unicast target and no broadcast, the socket is
+    /// `connect()`ed to it: sends skip the per-packet address lookup and the
+    /// OS reports ICMP port-unreachable as `ConnectionRefused`, so a dead
+    /// WLED shows up as send errors.
+    pub fn with_targets(port: u16, unicast: &[SocketAddr], broadcast: bool) -> Result<Self> {
+        let discovered = if broadcast {
+            discover_broadcast_targets(port)
+        } else {
+            Vec::new()
+        };
+        Self::build(unicast, discovered, broadcast)
+    }
+
+    /// Creates a broadcasting sender that starts from the targets in `cache`
+    /// and re-discovers in the background, updating the cache and switching
+    /// to the fresh set once discovery finishes.
+    ///
+    /// Cached addresses are sent to on `port`, whatever port they were
+    /// stored with. A missing, empty, or invalid cache, or one whose entries
+    /// can't be used (e.g. IPv6), falls back to discovering up front like
+    /// [`with_targets`](Self::with_targets) and then writing the cache.
+    /// Cache read or write failures are logged, never fatal.
+    pub fn with_cached_targets(
+        port: u16,
+        unicast: &[SocketAddr],
+        cache: TargetCache,
+    ) -> Result<Self> {
+        let mut cached: Vec<SocketAddr> = Vec::new();
+        match cache.load() {
+            Ok(entries) => {
+                for addr in entries.into_iter().map(|a| SocketAddr::new(a.ip(), port)) {
+                    if !cached.contains(&addr) {
+                        cached.push(addr);
+                    }
+                }
+            }
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    eprintln!("Warning: ignoring target cache: {e}");
+                }
+            }
+        }
+        let sender = if cached.is_empty() {
+            None
+        } else {
+            match Self::build(unicast, cached, true) {
+                Ok(sender) => Some(sender),
+                Err(e) => {
+                    eprintln!("Warning: ignoring target cache: {e}");
+                    None
+                }
+            }
+        };
+        let Some(mut sender) = sender else {
+            let discovered = discover_broadcast_targets(port);
+            if let Err(e) = cache.store(&discovered) {
+                eprintln!("Warning: could not write target cache: {e}");
+            }
+            return Self::build(unicast, discovered, true);
+        };
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let discovered = discover_broadcast_targets(port);
+            if let Err(e) = cache.store(&discovered) {
+                eprintln!("Warning: could not write target cache: {e}");
+            }
+            let _ = tx.send(discovered);
+        });
+        sender.discovery = Some(rx);
+        Ok(sender)
+    }
+
+    /// Creates a broadcasting sender for `unicast` plus already known
+    /// `broadcast` targets, without running discovery.
+    #[cfg(test)]
+    fn with_broadcast_targets(unicast: &[SocketAddr], broadcast: Vec<SocketAddr>) -> Result<Self> {
+        Self::build(unicast, broadcast, true)
+    }
+
+    fn build(
+        unicast: &[SocketAddr],
+        mut targets: Vec<SocketAddr>,
+        broadcast: bool,
+    ) -> Result<Self> {
+        for addr in unicast {
+            if !targets.contains(addr) {
+                targets.push(*addr);
+            }
+        }
+        // One socket sends to one address family, and broadcast is IPv4-only
+        let ipv6 = targets.iter().any(SocketAddr::is_ipv6);
+        if ipv6 && (broadcast || targets.iter().any(SocketAddr::is_ipv4)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "IPv6 targets can't be mixed with IPv4 targets or broadcast \
+                 (use only IPv6 targets with --no-broadcast)",
+            ));
+        }
+        let socket = UdpSocket::bind(any_addr(ipv6))?;
+        if !ipv6 {
+            // Needed for discovered broadcast addresses, and lets directed
+            // subnet broadcasts (e.g. 192.168.2.255) be given as targets. Set
+            // before connecting too: Linux refuses to connect to a broadcast
+            // address without it.
+            socket.set_broadcast(true)?;
+        }
+        let connected = !broadcast && targets.len() == 1;
+        if connected {
+            socket.connect(targets[0])?;
+        }
         Ok(Self {
             socket,
+            versions: vec![SyncVersion::V2; targets.len()],
             targets,
+            unicast: unicast.to_vec(),
+            connected,
             frame_counter: 0,
+            rate_limit: None,
+            rate_limited: 0,
+            would_block: 0,
+            discovery: None,
         })
     }
 
+    /// Replaces the broadcast targets with a freshly `discovered` set.
+    ///
+    /// Unicast targets are always kept, and every target that survives keeps
+    /// its packet format. Has no effect on a connected sender.
+    pub fn merge_discovered(&mut self, discovered: &[SocketAddr]) {
+        if self.connected {
+            return;
+        }
+        let mut targets = discovered.to_vec();
+        for addr in &self.unicast {
+            if !targets.contains(addr) {
+                targets.push(*addr);
+            }
+        }
+        self.versions = targets
+            .iter()
+            .map(|addr| {
+                self.targets
+                    .iter()
+                    .position(|t| t == addr)
+                    .map_or(SyncVersion::V2, |i| self.versions[i])
+            })
+            .collect();
+        self.targets = targets;
+    }
+
+    /// Picks up the result of a background re-discovery, if it has finished.
+    fn poll_discovery(&mut self) {
+        let Some(rx) = &self.discovery else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok(discovered) => {
+                self.merge_discovered(&discovered);
+                self.discovery = None;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.discovery = None,
+        }
+    }
+
+    /// Caps the send rate at `rate` packets per second, allowing bursts of
+    /// up to `burst` packets. Packets over the limit are skipped (not
+    /// queued) and don't advance the frame counter. `None` removes the cap.
+    pub fn set_rate_limit(&mut self, limit: Option<TokenBucket>) {
+        self.rate_limit = limit;
+    }
+
+    /// Number of packets skipped by the rate limiter so far.
+    pub fn rate_limited(&self) -> u64 {
+        self.rate_limited
+    }
+
+    /// Puts the socket in non-blocking mode, so a full send buffer on a
+    /// saturated link drops the packet (counted by
+    /// [`would_block_drops`](FrameSink::would_block_drops)) instead of
+    /// stalling the audio loop.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    /// Counts a would-block send as a dropped packet rather than an error.
+    fn absorb_would_block(&mut self, result: Result<()>) -> Result<()> {
+        match result {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                self.would_block += 1;
+                Ok(())
+            }
+            other => other,
+        }
+    }
+
     pub fn targets(&self) -> &[SocketAddr] {
         &self.targets
     }
 
+    /// Local address packets are sent from.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sets the packet format sent to `addr` (V2 by default), for networks
+    /// mixing old and new WLED builds.
+    ///
+    /// # Returns
+    /// `false` if `addr` is not one of the sender's targets.
+    pub fn set_target_version(&mut self, addr: SocketAddr, version: SyncVersion) -> bool {
+        match self.targets.iter().position(|t| *t == addr) {
+            Some(i) => {
+                self.versions[i] = version;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if packets go over a connected socket (single unicast
+    /// target).
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
     /// Sends an AudioSync packet to the target WLED device.
     ///
     /// Automatically increments the internal frame counter after each send.
@@ -128,49 +754,638 @@ impl UdpSender {
     /// * `Ok(())` - Packet sent successfully
     /// * `Err(io::Error)` - If UDP transmission fails
     pub fn send(&mut self, packet: &AudioSyncPacketV2) -> Result<()> {
-        let bytes = packet.to_bytes(self.frame_counter);
+        self.poll_discovery();
+        if let Some(bucket) = self.rate_limit.as_mut() {
+            if !bucket.try_acquire() {
+                self.rate_limited += 1;
+                return Ok(());
+            }
+        }
+        let v2 = packet.to_bytes(self.frame_counter);
+        let result = self.transmit(&v2, packet);
+        self.absorb_would_block(result)?;
+        self.frame_counter = self.frame_counter.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Re-sends a V2 packet received from another sender, for relays.
+    ///
+    /// V2 targets get `bytes` unchanged (including the original frame
+    /// counter); V1 targets get it re-encoded. The rate limit applies, and
+    /// the sender's own frame counter is not advanced.
+    ///
+    /// # Returns
+    /// * `Err(ErrorKind::InvalidData)` - If `bytes` is not a valid V2 packet
+    /// * Otherwise as [`send`](Self::send)
+    pub fn forward(&mut self, bytes: &[u8]) -> Result<()> {
+        let (packet, _) = AudioSyncPacketV2::from_bytes(bytes)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "not a V2 AudioSync packet"))?;
+        self.poll_discovery();
+        if let Some(bucket) = self.rate_limit.as_mut() {
+            if !bucket.try_acquire() {
+                self.rate_limited += 1;
+                return Ok(());
+            }
+        }
+        let result = self.transmit(bytes, &packet);
+        self.absorb_would_block(result)
+    }
+
+    /// Sends `v2` to every V2 target and the V1 encoding of `packet` to
+    /// every V1 target. Succeeds if at least one target was reached; fails
+    /// with `WouldBlock` if the only failures were full send buffers.
+    fn transmit(&self, v2: &[u8], packet: &AudioSyncPacketV2) -> Result<()> {
+        let v1 = self
+            .versions
+            .contains(&SyncVersion::V1)
+            .then(|| packet.to_bytes_v1());
+        let bytes_for = |version| match (version, &v1) {
+            (SyncVersion::V1, Some(v1)) => &v1[..],
+            _ => v2,
+        };
+        if self.connected {
+            self.socket.send(bytes_for(self.versions[0])).map_err(|e| {
+                if e.kind() == ErrorKind::ConnectionRefused {
+                    Error::new(
+                        e.kind(),
+                        format!("{} is not accepting packets ({e})", self.targets[0]),
+                    )
+                } else {
+                    e
+                }
+            })?;
+            return Ok(());
+        }
+
         let mut last_error = None;
+        let mut blocked = None;
         let mut any_sent = false;
 
-        for target in &self.targets {
-            match self.socket.send_to(&bytes, target) {
+        for (target, &version) in self.targets.iter().zip(&self.versions) {
+            match self.socket.send_to(bytes_for(version), target) {
                 Ok(_) => any_sent = true,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => blocked = Some(e),
                 Err(e) => last_error = Some(e),
             }
         }
 
         if !any_sent {
-            return Err(
-                last_error.unwrap_or_else(|| Error::other("No broadcast targets available"))
-            );
+            return Err(last_error
+                .or(blocked)
+                .unwrap_or_else(|| Error::other("No targets available")));
         }
+        Ok(())
+    }
+}
+
+impl FrameSink for UdpSender {
+    fn send(&mut self, packet: &AudioSyncPacketV2) -> Result<()> {
+        UdpSender::send(self, packet)
+    }
+
+    fn would_block_drops(&self) -> u64 {
+        self.would_block
+    }
+}
+
+/// Unix domain socket sender for local consumers on the same host.
+///
+/// Sends each packet as a single datagram in the same 44-byte V2 encoding used
+/// over UDP, avoiding the network stack for local visualizers and bridges.
+#[cfg(unix)]
+pub struct UnixSocketSender {
+    socket: UnixDatagram,
+    frame_counter: u8,
+}
+
+#[cfg(unix)]
+impl UnixSocketSender {
+    /// Connects to a listening Unix datagram socket at `path`.
+    ///
+    /// # Returns
+    /// * `Ok(UnixSocketSender)` - Connected sender with frame counter initialized to 0
+    /// * `Err(io::Error)` - If no socket is listening at `path`
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self {
+            socket,
+            frame_counter: 0,
+        })
+    }
+}
 
+#[cfg(unix)]
+impl FrameSink for UnixSocketSender {
+    fn send(&mut self, packet: &AudioSyncPacketV2) -> Result<()> {
+        let bytes = packet.to_bytes(self.frame_counter);
+        self.socket.send(&bytes)?;
         self.frame_counter = self.frame_counter.wrapping_add(1);
         Ok(())
     }
 }
 
 fn discover_broadcast_targets(port: u16) -> Vec<SocketAddr> {
+    let ifaces: Vec<(Ipv4Addr, Ipv4Addr)> = get_if_addrs()
+        .map(|ifaces| {
+            ifaces
+                .into_iter()
+                .filter_map(|iface| match iface.addr {
+                    IfAddr::V4(v4) => Some((v4.ip, v4.netmask)),
+                    IfAddr::V6(_) => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    broadcast_targets(&ifaces, port)
+}
+
+/// Builds the broadcast target set from `(ip, netmask)` interface pairs.
+///
+/// Always includes the limited broadcast `255.255.255.255`, plus one subnet
+/// broadcast per interface that has one (see [`subnet_broadcast`]).
+fn broadcast_targets(ifaces: &[(Ipv4Addr, Ipv4Addr)], port: u16) -> Vec<SocketAddr> {
     let mut unique = HashSet::new();
-    unique.insert(SocketAddr::V4(SocketAddrV4::new(
-        Ipv4Addr::new(255, 255, 255, 255),
-        port,
-    )));
-
-    if let Ok(ifaces) = get_if_addrs() {
-        for iface in ifaces {
-            if let IfAddr::V4(v4) = iface.addr {
-                if v4.ip.is_loopback() {
-                    continue;
-                }
+    unique.insert(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::BROADCAST, port)));
 
-                let ip_u32 = u32::from(v4.ip);
-                let mask_u32 = u32::from(v4.netmask);
-                let broadcast = Ipv4Addr::from(ip_u32 | !mask_u32);
-                unique.insert(SocketAddr::V4(SocketAddrV4::new(broadcast, port)));
-            }
+    for &(ip, netmask) in ifaces {
+        if let Some(broadcast) = subnet_broadcast(ip, netmask) {
+            unique.insert(SocketAddr::V4(SocketAddrV4::new(broadcast, port)));
         }
     }
 
     unique.into_iter().collect()
 }
+
+/// Returns the directed broadcast address of the subnet `ip` belongs to.
+///
+/// Returns `None` where `ip | !mask` would not be a real broadcast address:
+/// loopback and unspecified addresses, and point-to-point /31 and /32 links
+/// (RFC 3021), where it would yield the peer's or the host's own address.
+fn subnet_broadcast(ip: Ipv4Addr, netmask: Ipv4Addr) -> Option<Ipv4Addr> {
+    if ip.is_loopback() || ip.is_unspecified() {
+        return None;
+    }
+
+    let mask_u32 = u32::from(netmask);
+    if mask_u32.leading_ones() >= 31 {
+        return None;
+    }
+
+    Some(Ipv4Addr::from(u32::from(ip) | !mask_u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_packet() -> AudioSyncPacketV2 {
+        AudioSyncPacketV2 {
+            sample_raw: 12.5,
+            sample_smth: 10.0,
+            sample_peak: 1,
+            fft_result: [7; 16],
+            zero_crossing_count: 42,
+            fft_magnitude: 3.25,
+            fft_major_peak: 440.0,
+        }
+    }
+
+    fn frame_with_peak(sample_peak: u8) -> DspFrame {
+        DspFrame {
+            sample_raw: 100.0,
+            sample_smth: 80.0,
+            sample_peak,
+            fft_result: [128; 16],
+            zero_crossing_count: 10,
+            fft_magnitude: 5.0,
+            fft_major_peak: 120.0,
+            peak_hold: [128; 16],
+            fft_magnitude_norm: None,
+            estimated_bpm: 0.0,
+            spectral_centroid: 0.0,
+            fft_result_right: None,
+        }
+    }
+
+    #[test]
+    fn test_beat_gate_emits_only_beats() {
+        let frames: Vec<DspFrame> = [0, 0, 1, 0, 0, 0, 1, 0]
+            .iter()
+            .map(|&p| frame_with_peak(p))
+            .collect();
+
+        let suppressed: Vec<_> = frames
+            .iter()
+            .filter_map(|f| beat_gate(f, BeatIdle::Suppress))
+            .collect();
+        assert_eq!(suppressed.len(), 2);
+        assert!(suppressed.iter().all(|p| p.sample_peak == 1));
+
+        let zeroed: Vec<_> = frames
+            .iter()
+            .filter_map(|f| beat_gate(f, BeatIdle::Zero))
+            .collect();
+        assert_eq!(zeroed.len(), frames.len());
+        let flashes = zeroed.iter().filter(|p| p.fft_result == [128; 16]).count();
+        let idle = zeroed.iter().filter(|p| p.fft_result == [0; 16]).count();
+        assert_eq!((flashes, idle), (2, 6));
+    }
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> Ipv4Addr {
+        Ipv4Addr::new(a, b, c, d)
+    }
+
+    fn target(ip: Ipv4Addr) -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(ip, 11988))
+    }
+
+    #[test]
+    fn test_broadcast_targets_slash_24() {
+        let targets = broadcast_targets(&[(v4(192, 168, 1, 42), v4(255, 255, 255, 0))], 11988);
+        assert_eq!(targets.len(), 2);
+        assert!(targets.contains(&target(v4(192, 168, 1, 255))));
+        assert!(targets.contains(&target(Ipv4Addr::BROADCAST)));
+    }
+
+    #[test]
+    fn test_broadcast_targets_point_to_point() {
+        // /31 and /32 links have no subnet broadcast; only the limited broadcast remains
+        let ifaces = [
+            (v4(10, 0, 0, 0), v4(255, 255, 255, 254)),
+            (v4(10, 8, 0, 5), v4(255, 255, 255, 255)),
+        ];
+        let targets = broadcast_targets(&ifaces, 11988);
+        assert_eq!(targets, vec![target(Ipv4Addr::BROADCAST)]);
+    }
+
+    #[test]
+    fn test_broadcast_targets_skip_loopback() {
+        let targets = broadcast_targets(&[(v4(127, 0, 0, 1), v4(255, 0, 0, 0))], 11988);
+        assert_eq!(targets, vec![target(Ipv4Addr::BROADCAST)]);
+    }
+
+    #[test]
+    fn test_with_targets_merges_broadcast_and_unicast() {
+        let unicast = SocketAddr::from(([10, 20, 30, 40], 12000));
+        let sender = UdpSender::with_targets(11988, &[unicast, unicast], true).unwrap();
+        let targets = sender.targets();
+
+        assert!(targets.contains(&target(Ipv4Addr::BROADCAST)));
+        assert!(targets.contains(&unicast));
+        assert_eq!(targets.iter().filter(|&&t| t == unicast).count(), 1);
+
+        let unicast_only = UdpSender::with_targets(11988, &[unicast], false).unwrap();
+        assert_eq!(unicast_only.targets(), &[unicast]);
+        assert!(!sender.is_connected());
+    }
+
+    #[test]
+    fn test_target_cache_seeds_sender_and_discovery_updates_it() {
+        let path = std::env::temp_dir().join(format!("wled-targets-{}.txt", std::process::id()));
+        let cache = TargetCache::new(&path);
+        let stale = target(Ipv4Addr::new(192, 168, 1, 255));
+        let kept = target(Ipv4Addr::BROADCAST);
+        cache.store(&[stale, kept]).unwrap();
+
+        let unicast = SocketAddr::from(([10, 20, 30, 40], 12000));
+        let mut sender =
+            UdpSender::with_broadcast_targets(&[unicast], cache.load().unwrap()).unwrap();
+        assert_eq!(sender.targets(), &[stale, kept, unicast]);
+        sender.set_target_version(kept, SyncVersion::V1);
+
+        // Discovery drops the stale subnet, keeps the unicast target and
+        // the per-target packet format
+        let fresh = target(Ipv4Addr::new(10, 0, 0, 255));
+        sender.merge_discovered(&[kept, fresh]);
+        assert_eq!(sender.targets(), &[kept, fresh, unicast]);
+        assert_eq!(
+            sender.versions,
+            [SyncVersion::V1, SyncVersion::V2, SyncVersion::V2]
+        );
+
+        std::fs::write(
+            &path,
+            "# comment\n\n255.255.255.255:11988\nnot-an-address\n",
+        )
+        .unwrap();
+        let err = cache.load().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cache.load().unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_cached_targets_use_current_port_and_skip_unusable_entries() {
+        let path =
+            std::env::temp_dir().join(format!("wled-targets-port-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "192.168.1.255:21324
+192.168.1.255:11988
+",
+        )
+        .unwrap();
+        let sender = UdpSender::with_cached_targets(11988, &[], TargetCache::new(&path)).unwrap();
+        assert_eq!(sender.targets(), &[target(Ipv4Addr::new(192, 168, 1, 255))]);
+
+        // An IPv6 entry can't be broadcast to, so discovery runs instead
+        std::fs::write(
+            &path,
+            "[::1]:11988
+",
+        )
+        .unwrap();
+        let sender = UdpSender::with_cached_targets(11988, &[], TargetCache::new(&path)).unwrap();
+        assert!(sender.targets().contains(&target(Ipv4Addr::BROADCAST)));
+        assert!(sender.targets().iter().all(SocketAddr::is_ipv4));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_target_cache_is_written_after_discovery() {
+        let path =
+            std::env::temp_dir().join(format!("wled-targets-new-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sender = UdpSender::with_cached_targets(11988, &[], TargetCache::new(&path)).unwrap();
+
+        let cached = TargetCache::new(&path).load().unwrap();
+        assert!(cached.contains(&target(Ipv4Addr::BROADCAST)));
+        assert_eq!(sender.targets(), &cached[..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_would_block_is_counted_not_reported() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 9));
+        let mut sender = UdpSender::with_targets(11988, &[addr], false).unwrap();
+        sender.set_nonblocking(true).unwrap();
+
+        let full = Err(Error::from(ErrorKind::WouldBlock));
+        assert!(sender.absorb_would_block(full).is_ok());
+        assert!(sender.absorb_would_block(Ok(())).is_ok());
+        assert_eq!(sender.would_block_drops(), 1);
+
+        let refused = Err(Error::from(ErrorKind::ConnectionRefused));
+        assert!(sender.absorb_would_block(refused).is_err());
+        assert_eq!(sender.would_block_drops(), 1);
+    }
+
+    #[test]
+    fn test_single_target_uses_connected_socket() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(std::time::Duration::from_secs(2)))
+            .unwrap();
+        let addr = receiver.local_addr().unwrap();
+
+        let mut sender = UdpSender::with_targets(11988, &[addr], false).unwrap();
+        assert!(sender.is_connected());
+
+        let pkt = test_packet();
+        sender.send(&pkt).unwrap();
+        sender.send(&pkt).unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &pkt.to_bytes(0));
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &pkt.to_bytes(1));
+    }
+
+    #[test]
+    fn test_single_broadcast_target_connects() {
+        // e.g. --target 192.168.1.255 --no-broadcast, or a bridge relaying
+        // to one subnet
+        let target: SocketAddr = "255.255.255.255:11988".parse().unwrap();
+        let sender = UdpSender::with_targets(11988, &[target], false).unwrap();
+        assert!(sender.is_connected());
+        assert_eq!(sender.targets(), [target]);
+    }
+
+    #[test]
+    fn test_ipv6_targets_use_an_ipv6_socket() {
+        let v6: SocketAddr = "[::1]:11988".parse().unwrap();
+        let v4: SocketAddr = "127.0.0.1:11988".parse().unwrap();
+
+        // Some hosts have IPv6 disabled entirely; nothing to check there
+        if let Ok(sender) = UdpSender::with_targets(11988, &[v6], false) {
+            assert!(sender.is_connected());
+            assert!(sender.socket.local_addr().unwrap().is_ipv6());
+        }
+        for (targets, broadcast) in [(&[v6, v4][..], false), (&[v6][..], true)] {
+            let err = UdpSender::with_targets(11988, targets, broadcast).err();
+            assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::InvalidInput));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_connected_send_reports_refused() {
+        // Grab a free port, then close it so nothing listens there
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut sender = UdpSender::with_targets(11988, &[addr], false).unwrap();
+
+        // The ICMP error arrives asynchronously and fails a later send
+        let refused = (0..20).find_map(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            sender.send(&test_packet()).err()
+        });
+        assert_eq!(
+            refused.map(|e| e.kind()),
+            Some(ErrorKind::ConnectionRefused)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_sender_delivers_packet() {
+        let path = std::env::temp_dir().join(format!("wled-sink-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+
+        let mut sink = UnixSocketSender::connect(&path).unwrap();
+        let pkt = test_packet();
+        FrameSink::send(&mut sink, &pkt).unwrap();
+        FrameSink::send(&mut sink, &pkt).unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &pkt.to_bytes(0));
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &pkt.to_bytes(1));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_check_port_flags_mismatched_protocols() {
+        assert!(check_port(WledProtocol::AudioSync, 80).is_some());
+        assert!(check_port(WledProtocol::AudioSync, 21324).is_some());
+        assert!(check_port(WledProtocol::Realtime, 11988).is_some());
+        assert!(check_port(WledProtocol::Json, 0).is_some());
+
+        for protocol in WledProtocol::ALL {
+            assert_eq!(check_port(protocol, protocol.default_port()), None);
+        }
+        // Custom ports are not second-guessed
+        assert_eq!(check_port(WledProtocol::AudioSync, 12000), None);
+    }
+
+    #[test]
+    fn test_check_targets_with_mock_probe() {
+        let good = SocketAddr::from(([127, 0, 0, 1], 11988));
+        let bogus = SocketAddr::from(([203, 0, 113, 7], 11988));
+        let results = check_targets(&[good, bogus], |addr| {
+            if addr == good {
+                Ok(())
+            } else {
+                Err(Error::new(ErrorKind::HostUnreachable, "no route"))
+            }
+        });
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, good);
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].0, bogus);
+        assert_eq!(
+            results[1].1.as_ref().unwrap_err().kind(),
+            ErrorKind::HostUnreachable
+        );
+    }
+
+    #[test]
+    fn test_probe_udp_loopback_listener_is_reachable() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(probe_udp(listener.local_addr().unwrap()).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_probe_udp_closed_port_is_refused() {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let err = probe_udp(addr).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn test_token_bucket_flood_burst_and_long_run_rate() {
+        let mut bucket = TokenBucket::new(50.0, 5);
+        let start = Instant::now();
+
+        // A flood at one instant only drains the burst
+        let burst = (0..100).filter(|_| bucket.try_acquire_at(start)).count();
+        assert_eq!(burst, 5);
+
+        // Flooding at 1 kHz for 10 s passes ~50/s on average
+        let sent = (1..=10_000)
+            .filter(|&ms| bucket.try_acquire_at(start + Duration::from_millis(ms)))
+            .count();
+        assert!((499..=501).contains(&sent), "sent {sent}");
+
+        // After idling, at most `burst` packets pass back to back
+        let later = start + Duration::from_secs(20);
+        let burst = (0..100).filter(|_| bucket.try_acquire_at(later)).count();
+        assert_eq!(burst, 5);
+    }
+
+    #[test]
+    fn test_udp_sender_rate_limit_skips_excess() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut sender = UdpSender::with_targets(addr.port(), &[addr], false).unwrap();
+        // No refill: only the burst gets through
+        sender.set_rate_limit(Some(TokenBucket::new(0.0, 2)));
+        for _ in 0..5 {
+            sender.send(&test_packet()).unwrap();
+        }
+        assert_eq!(sender.rate_limited(), 3);
+
+        let mut buf = [0u8; 64];
+        let counters: Vec<u8> = (0..2)
+            .map(|_| {
+                listener.recv(&mut buf).unwrap();
+                buf[17]
+            })
+            .collect();
+        assert_eq!(counters, [0, 1]);
+        assert!(listener.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_static_bins_rejects_wrong_length() {
+        assert!(parse_static_bins("1,2,3").unwrap_err().contains("got 3"));
+        let seventeen = vec!["0"; 17].join(",");
+        assert!(parse_static_bins(&seventeen)
+            .unwrap_err()
+            .contains("got 17"));
+        let bad = format!("{},256", vec!["0"; 15].join(","));
+        assert!(parse_static_bins(&bad).unwrap_err().contains("'256'"));
+    }
+
+    #[test]
+    fn test_static_packet_carries_bins() {
+        let bins =
+            parse_static_bins("0,16,32,48,64,80,96,112,128,144,160,176,192,208,224,255").unwrap();
+        let bytes = static_packet(&bins).to_bytes(0);
+        assert_eq!(&bytes[18..34], &bins);
+        assert_eq!(bytes[16], 0);
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let bytes = test_packet().to_bytes_v1();
+        assert_eq!(&bytes[..6], b"00001\0");
+        assert!(bytes[6..40].iter().all(|&b| b == 0));
+        assert_eq!(i32::from_le_bytes(bytes[44..48].try_into().unwrap()), 12);
+        assert_eq!(bytes[52], 1);
+        assert_eq!(&bytes[53..69], &test_packet().fft_result);
+        assert_eq!(
+            f64::from_le_bytes(bytes[80..88].try_into().unwrap()),
+            f64::from(test_packet().fft_major_peak)
+        );
+    }
+
+    #[test]
+    fn test_udp_sender_mixed_versions() {
+        let old = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let new = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for s in [&old, &new] {
+            s.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        }
+        let (old_addr, new_addr) = (old.local_addr().unwrap(), new.local_addr().unwrap());
+        let mut sender = UdpSender::with_targets(0, &[old_addr, new_addr], false).unwrap();
+        assert!(sender.set_target_version(old_addr, SyncVersion::V1));
+        assert!(!sender.set_target_version("127.0.0.1:9".parse().unwrap(), SyncVersion::V1));
+        sender.send(&test_packet()).unwrap();
+
+        let mut buf = [0u8; 128];
+        let n = old.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &test_packet().to_bytes_v1()[..]);
+        let n = new.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &test_packet().to_bytes(0)[..]);
+    }
+
+    #[test]
+    fn test_from_bytes_roundtrip() {
+        let bytes = test_packet().to_bytes(77);
+        let (packet, counter) = AudioSyncPacketV2::from_bytes(&bytes).unwrap();
+        assert_eq!(counter, 77);
+        assert_eq!(packet.to_bytes(77), bytes);
+
+        assert!(AudioSyncPacketV2::from_bytes(&bytes[..43]).is_none());
+        let mut bad = bytes;
+        bad[4] = b'1';
+        assert!(AudioSyncPacketV2::from_bytes(&bad).is_none());
+    }
+}
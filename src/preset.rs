@@ -0,0 +1,209 @@
+//! Beat-triggered WLED preset changes over the JSON HTTP API.
+//!
+//! Enabled with the `http` cargo feature. On a detected beat a
+//! [`PresetTrigger`] asks WLED to apply a preset by posting `{"ps":ID}` to
+//! `/json/state`, at most once per minimum interval so the device isn't
+//! flooded with state changes on every kick drum. Requests run on a
+//! short-lived thread so a slow or unreachable device never stalls the audio
+//! loop.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// WLED's HTTP port.
+pub const DEFAULT_HTTP_PORT: u16 = 80;
+
+/// Connect, read, and write timeout for one preset request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Debounces beats to at most one trigger per `min_interval`.
+#[derive(Clone, Debug)]
+pub struct BeatDebounce {
+    min_interval: Duration,
+    last: Option<Instant>,
+}
+
+impl BeatDebounce {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last: None,
+        }
+    }
+
+    /// Returns `true` if a beat at `now` should fire, which it does unless
+    /// the previous trigger was less than `min_interval` ago.
+    pub fn fire_at(&mut self, beat: bool, now: Instant) -> bool {
+        if !beat {
+            return false;
+        }
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < self.min_interval)
+        {
+            return false;
+        }
+        self.last = Some(now);
+        true
+    }
+}
+
+/// Parses `HOST` or `HOST:PORT` (default port 80) for `--wled-host`.
+pub fn parse_http_host(s: &str) -> Result<SocketAddr, String> {
+    let with_port = if s
+        .rsplit_once(':')
+        .is_some_and(|(_, p)| p.parse::<u16>().is_ok())
+    {
+        s.to_string()
+    } else {
+        format!("{s}:{DEFAULT_HTTP_PORT}")
+    };
+    with_port
+        .to_socket_addrs()
+        .map_err(|e| format!("invalid host '{s}': {e}"))?
+        .next()
+        .ok_or_else(|| format!("'{s}' did not resolve to an address"))
+}
+
+/// Applies `preset` on the WLED instance at `addr`, waiting for its reply.
+///
+/// # Returns
+/// * `Ok(())` - WLED answered with a 2xx status
+/// * `Err(io::Error)` - On connection failure, timeout, or an error status
+pub fn apply_preset(addr: SocketAddr, preset: u8) -> io::Result<()> {
+    let mut stream = TcpStream::connect_timeout(&addr, REQUEST_TIMEOUT)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let body = format!("{{\"ps\":{preset}}}");
+    write!(
+        stream,
+        "POST /json/state HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        addr.ip(),
+        body.len()
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status = status_line.split_whitespace().nth(1).unwrap_or("");
+    if status.starts_with('2') {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "WLED replied '{}'",
+            status_line.trim_end()
+        )))
+    }
+}
+
+/// Fires a preset change on beats, rate limited by a [`BeatDebounce`].
+pub struct PresetTrigger {
+    addr: SocketAddr,
+    preset: u8,
+    debounce: BeatDebounce,
+}
+
+impl PresetTrigger {
+    pub fn new(addr: SocketAddr, preset: u8, min_interval: Duration) -> Self {
+        Self {
+            addr,
+            preset,
+            debounce: BeatDebounce::new(min_interval),
+        }
+    }
+
+    /// Feeds one frame's `sample_peak`. On a beat that passes the
+    /// debounce, applies the preset in the background and returns `true`;
+    /// request errors are logged to stderr.
+    pub fn on_frame(&mut self, sample_peak: u8) -> bool {
+        if !self.debounce.fire_at(sample_peak != 0, Instant::now()) {
+            return false;
+        }
+        let (addr, preset) = (self.addr, self.preset);
+        thread::spawn(move || {
+            if let Err(e) = apply_preset(addr, preset) {
+                eprintln!("Preset {preset} on {addr} failed: {e}");
+            }
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_debounce_fires_at_most_once_per_interval() {
+        let mut debounce = BeatDebounce::new(Duration::from_secs(2));
+        let start = Instant::now();
+
+        // A beat every 100ms for 5 seconds fires at 0s, 2s, and 4s
+        let fired: Vec<u64> = (0..50)
+            .map(|i| i * 100)
+            .filter(|&ms| debounce.fire_at(true, start + Duration::from_millis(ms)))
+            .collect();
+        assert_eq!(fired, vec![0, 2000, 4000]);
+
+        // Frames without a beat never fire
+        assert!(!debounce.fire_at(false, start + Duration::from_secs(60)));
+        assert!(debounce.fire_at(true, start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_parse_http_host() {
+        assert_eq!(
+            parse_http_host("127.0.0.1"),
+            Ok("127.0.0.1:80".parse().unwrap())
+        );
+        assert_eq!(
+            parse_http_host("127.0.0.1:8080"),
+            Ok("127.0.0.1:8080".parse().unwrap())
+        );
+        assert!(parse_http_host("not a host").is_err());
+    }
+
+    fn serve_once(reply: &'static str) -> (SocketAddr, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let mut request = String::new();
+            while !request.ends_with('}') {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+            }
+            stream.write_all(reply.as_bytes()).unwrap();
+            request
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_apply_preset_posts_json_state() {
+        let (addr, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        apply_preset(addr, 7).unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /json/state HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Length: 8\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"ps\":7}"));
+    }
+
+    #[test]
+    fn test_apply_preset_reports_error_status() {
+        let (addr, server) = serve_once("HTTP/1.1 500 Internal Server Error\r\n\r\n");
+        let err = apply_preset(addr, 1).unwrap_err();
+        assert!(err.to_string().contains("500"));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use crate::audio::CaptureOptions;
+use crate::dsp::{DspConfig, DspProcessor, PresenceBoost, AGC_SPAN_FLOOR, BIN_FLOOR};
+
+/// A named bundle of level and sensitivity settings for one kind of source.
+///
+/// Sources differ a lot in loudness and content (mastered music vs. film
+/// mixes vs. games), so a profile captures the trim, AGC, EQ, and beat
+/// sensitivity that suit one of them and applies it in one step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainProfile {
+    pub name: &'static str,
+    /// Input trim in dB (see [`DspProcessor::set_input_gain_db`])
+    pub input_gain_db: f32,
+    /// Fixed AGC envelope, or `None` for adaptive AGC
+    pub agc_range: Option<(f32, f32)>,
+    /// Optional presence EQ (see [`DspProcessor::set_presence_boost`])
+    pub presence: Option<PresenceBoost>,
+    /// Beat threshold factor (see [`DspProcessor::set_beat_threshold`])
+    pub beat_threshold: f32,
+}
+
+/// Built-in profiles selectable with `--profile`.
+pub const BUILTIN_PROFILES: &[GainProfile] = &[
+    // Mastered music is loud and dense: defaults throughout
+    GainProfile {
+        name: "music",
+        input_gain_db: 0.0,
+        agc_range: None,
+        presence: None,
+        beat_threshold: 1.2,
+    },
+    // Film mixes sit lower with dialog in the presence range; require a
+    // stronger hit before flashing on effects
+    GainProfile {
+        name: "movies",
+        input_gain_db: 6.0,
+        agc_range: None,
+        presence: Some(PresenceBoost {
+            freq_lo: 1000.0,
+            freq_hi: 4000.0,
+            gain: 1.5,
+        }),
+        beat_threshold: 1.4,
+    },
+    // Games are often hot with constant low-end rumble
+    GainProfile {
+        name: "games",
+        input_gain_db: -3.0,
+        agc_range: None,
+        presence: None,
+        beat_threshold: 1.3,
+    },
+];
+
+impl GainProfile {
+    /// Looks up a built-in profile by name (case-insensitive).
+    pub fn builtin(name: &str) -> Option<&'static GainProfile> {
+        BUILTIN_PROFILES
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Applies every setting in this profile to `dsp`.
+    pub fn apply(&self, dsp: &mut DspProcessor) {
+        dsp.set_input_gain_db(self.input_gain_db);
+        match self.agc_range {
+            Some((min, max)) => dsp.set_agc_range(min, max),
+            None => dsp.set_agc_frozen(false),
+        }
+        dsp.set_presence_boost(self.presence);
+        dsp.set_beat_threshold(self.beat_threshold);
+    }
+}
+
+/// Highest `--sensitivity` level.
+pub const MAX_SENSITIVITY: u8 = 100;
+
+/// One-knob reactivity control mapped onto input trim, the AGC span floor,
+/// the bin noise gate, and the post-AGC output gain.
+///
+/// | Level | Input trim | AGC span floor | Bin floor | Output gain |
+/// |-------|------------|----------------|-----------|-------------|
+/// | 0     | -12 dB     | 1000           | 8         | 0.5         |
+/// | 50    | 0 dB       | 1              | 2         | 1.0         |
+/// | 100   | +12 dB     | ~0.03          | 0         | 2.0         |
+///
+/// Level 50 is exactly the defaults ([`AGC_SPAN_FLOOR`], [`BIN_FLOOR`]).
+/// Trim, span floor and output gain move log-linearly and the bin floor
+/// linearly on each side of it. Low levels keep quiet material dim and gate
+/// more noise. High levels boost the volume and stretch even faint input to
+/// full scale; since the adaptive AGC cancels most of the trim on normal
+/// material, the output gain is what brightens it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sensitivity {
+    /// Input trim in dB (see [`DspProcessor::set_input_gain_db`])
+    pub input_gain_db: f32,
+    /// See [`DspProcessor::set_agc_span_floor`]
+    pub agc_span_floor: f32,
+    /// See [`DspProcessor::set_bin_floor`]
+    pub bin_floor: u8,
+    /// See [`DspProcessor::set_output_gain`]
+    pub output_gain: f32,
+}
+
+impl Sensitivity {
+    /// Maps a level in 0..=100 (clamped) to DSP parameters.
+    pub fn from_level(level: u8) -> Self {
+        let t = f32::from(level.min(MAX_SENSITIVITY)) / f32::from(MAX_SENSITIVITY);
+        // Decades of span floor above the default at level 0, and below it
+        // at level 100
+        let (span_decades, bin_floor) = if t <= 0.5 {
+            (
+                3.0 * (1.0 - t * 2.0),
+                8.0 - (8.0 - f32::from(BIN_FLOOR)) * t * 2.0,
+            )
+        } else {
+            (
+                -1.5 * (t * 2.0 - 1.0),
+                f32::from(BIN_FLOOR) * (1.0 - t) * 2.0,
+            )
+        };
+        Self {
+            input_gain_db: -12.0 + 24.0 * t,
+            agc_span_floor: AGC_SPAN_FLOOR * 10f32.powf(span_decades),
+            bin_floor: bin_floor.round() as u8,
+            // Halved at level 0, doubled at level 100
+            output_gain: 2f32.powf(t * 2.0 - 1.0),
+        }
+    }
+
+    /// Applies the mapped settings to `dsp`.
+    pub fn apply(&self, dsp: &mut DspProcessor) {
+        dsp.set_input_gain_db(self.input_gain_db);
+        dsp.set_agc_span_floor(self.agc_span_floor);
+        dsp.set_bin_floor(self.bin_floor);
+        dsp.set_output_gain(self.output_gain);
+    }
+}
+
+/// A bundle of analysis and buffering sizes trading frequency resolution
+/// against responsiveness, so users can pick "responsive" or "smooth"
+/// without tuning each buffer.
+///
+/// | Preset        | FFT size (hop) | Capture buffer | Capture queue | Frames/s at 48 kHz |
+/// |---------------|----------------|----------------|---------------|--------------------|
+/// | (defaults)    | 2048 (1024)    | driver default | 8 chunks      | ~47                |
+/// | `low-latency` | 1024 (512)     | 256 frames     | 2 chunks      | ~94                |
+/// | `smooth`      | 4096 (2048)    | 1024 frames    | 16 chunks     | ~23                |
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencyPreset {
+    pub name: &'static str,
+    /// FFT length; the hop is half of it
+    pub fft_size: usize,
+    /// Frames per driver callback (see [`CaptureOptions::buffer_frames`])
+    pub buffer_frames: u32,
+    /// Capture queue length (see [`CaptureOptions::queue_len`])
+    pub queue_len: usize,
+}
+
+impl LatencyPreset {
+    /// `--low-latency`: half the default FFT, small buffers.
+    pub const LOW_LATENCY: Self = Self {
+        name: "low-latency",
+        fft_size: 1024,
+        buffer_frames: 256,
+        queue_len: 2,
+    };
+
+    /// `--smooth`: twice the default FFT, generous buffers.
+    pub const SMOOTH: Self = Self {
+        name: "smooth",
+        fft_size: 4096,
+        buffer_frames: 1024,
+        queue_len: 16,
+    };
+
+    /// Default DSP tuning at this preset's FFT size.
+    pub fn dsp_config(&self) -> DspConfig {
+        DspConfig {
+            fft_size: self.fft_size,
+            hop_size: self.fft_size / 2,
+            ..DspConfig::default()
+        }
+    }
+
+    /// `base` with this preset's buffer sizes.
+    pub fn capture_options(&self, base: CaptureOptions) -> CaptureOptions {
+        CaptureOptions {
+            buffer_frames: Some(self.buffer_frames),
+            queue_len: self.queue_len,
+            ..base
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_applies_bundled_parameters() {
+        let profile = GainProfile::builtin("Movies").unwrap();
+        let mut dsp = DspProcessor::new(48000);
+        profile.apply(&mut dsp);
+
+        assert!((dsp.input_gain_db() - 6.0).abs() < 1e-4);
+        assert_eq!(dsp.beat_threshold(), 1.4);
+        assert!(!dsp.is_agc_frozen());
+    }
+
+    #[test]
+    fn test_profile_with_fixed_agc_freezes() {
+        let profile = GainProfile {
+            agc_range: Some((1.0, 30.0)),
+            ..GainProfile::builtin("music").unwrap().clone()
+        };
+        let mut dsp = DspProcessor::new(48000);
+        profile.apply(&mut dsp);
+
+        assert!(dsp.is_agc_frozen());
+        assert_eq!(dsp.agc_range(), (1.0, 30.0));
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        assert!(GainProfile::builtin("podcast").is_none());
+    }
+
+    #[test]
+    fn test_low_latency_preset_doubles_frame_rate() {
+        let preset = LatencyPreset::LOW_LATENCY;
+        assert_eq!(
+            Ok(preset.dsp_config()),
+            DspConfig::for_fft_size(preset.fft_size)
+        );
+        let mut fast = DspProcessor::with_config(48000, preset.dsp_config());
+        assert_eq!(fast.config().fft_size, 1024);
+        assert_eq!(fast.hop_size(), 512);
+
+        let second = vec![0.1f32; 48000];
+        let fast_frames = fast.push_samples(&second).len();
+        let default_frames = DspProcessor::new(48000).push_samples(&second).len();
+        assert_eq!(fast_frames, 92);
+        assert_eq!(default_frames, 45);
+
+        let options = preset.capture_options(CaptureOptions::default());
+        assert_eq!(options.buffer_frames, Some(256));
+        assert_eq!(options.queue_len, 2);
+        let smooth = LatencyPreset::SMOOTH.dsp_config();
+        assert_eq!(Ok(smooth.clone()), DspConfig::for_fft_size(4096));
+        assert_eq!(smooth.hop_size, 2048);
+    }
+
+    #[test]
+    fn test_sensitivity_mapping_endpoints() {
+        let low = Sensitivity::from_level(0);
+        assert_eq!(low.input_gain_db, -12.0);
+        assert!((low.agc_span_floor - 1000.0).abs() < 0.1);
+        assert_eq!(low.bin_floor, 8);
+        assert_eq!(low.output_gain, 0.5);
+
+        let high = Sensitivity::from_level(200);
+        assert_eq!(high, Sensitivity::from_level(100));
+        assert_eq!(high.input_gain_db, 12.0);
+        assert!((high.agc_span_floor - 0.0316).abs() < 1e-3);
+        assert_eq!(high.bin_floor, 0);
+        assert_eq!(high.output_gain, 2.0);
+    }
+
+    #[test]
+    fn test_sensitivity_50_is_the_defaults() {
+        let dsp = DspProcessor::new(48000);
+        assert_eq!(
+            Sensitivity::from_level(50),
+            Sensitivity {
+                input_gain_db: 0.0,
+                agc_span_floor: AGC_SPAN_FLOOR,
+                bin_floor: BIN_FLOOR,
+                output_gain: 1.0,
+            }
+        );
+        assert_eq!(dsp.agc_span_floor(), AGC_SPAN_FLOOR);
+
+        // Passing --sensitivity 50 behaves like not passing it at all
+        let samples: Vec<f32> = (0..24000).map(|i| (i as f32 * 0.02).sin() * 0.05).collect();
+        let mut tuned = DspProcessor::new(48000);
+        Sensitivity::from_level(50).apply(&mut tuned);
+        let expected = DspProcessor::new(48000).push_samples(&samples);
+        let frames = tuned.push_samples(&samples);
+        for (a, b) in frames.iter().zip(&expected) {
+            assert_eq!(a.fft_result, b.fft_result);
+        }
+    }
+
+    #[test]
+    fn test_sensitivity_raises_output_monotonically() {
+        // Moderate mix: bass and treble tones around -30 dBFS
+        let samples: Vec<f32> = (0..48000)
+            .map(|i| {
+                let t = i as f32 / 48000.0;
+                0.03 * (2.0 * std::f32::consts::PI * 120.0 * t).sin()
+                    + 0.01 * (2.0 * std::f32::consts::PI * 2500.0 * t).sin()
+            })
+            .collect();
+        let average = |level: u8| {
+            let mut dsp = DspProcessor::new(48000);
+            Sensitivity::from_level(level).apply(&mut dsp);
+            let frames = dsp.push_samples(&samples);
+            let total: u32 = frames
+                .iter()
+                .flat_map(|f| f.fft_result.iter().map(|&b| u32::from(b)))
+                .sum();
+            total as f32 / frames.len() as f32 / 16.0
+        };
+
+        let levels: Vec<f32> = (0..=100).step_by(10).map(average).collect();
+        for pair in levels.windows(2) {
+            assert!(pair[1] >= pair[0] - 0.1, "not monotonic: {levels:?}");
+        }
+        assert!(levels[10] > levels[0] * 2.0, "{levels:?}");
+        assert!(levels[5] > levels[2], "{levels:?}");
+        // The upper half still brightens material the AGC already spans
+        assert!(levels[6] > levels[5], "{levels:?}");
+        assert!(levels[10] > levels[6] * 1.15, "{levels:?}");
+    }
+}
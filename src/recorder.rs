@@ -0,0 +1,231 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Size of the canonical 16-bit PCM WAV header in bytes.
+const HEADER_LEN: u32 = 44;
+
+/// How often (in seconds of audio) the header length fields are rewritten.
+///
+/// Keeping the header current means an abrupt kill leaves a file that is at
+/// most this much shorter than what was actually written, instead of one that
+/// claims zero length.
+const HEADER_UPDATE_SECS: u32 = 1;
+
+/// Most data bytes a WAV file can hold: the RIFF size field, which counts
+/// everything after itself, is a u32.
+const MAX_DATA_LEN: u64 = u32::MAX as u64 - (HEADER_LEN as u64 - 8);
+
+/// Records captured audio (mono, or interleaved multi-channel) to a 16-bit
+/// PCM WAV file.
+///
+/// The RIFF and data chunk sizes are patched periodically while recording and
+/// once more by [`finalize`](Self::finalize), so the file stays readable even
+/// if the process does not shut down cleanly. A WAV file can't hold more
+/// than 4 GiB (about 12 hours of 48 kHz mono), so recording stops with an
+/// error once it is full.
+///
+/// # Example
+/// ```no_run
+/// use wled_audio_server::recorder::WavRecorder;
+///
+/// let mut rec = WavRecorder::create("capture.wav", 48000)?;
+/// rec.write_samples(&[0.0, 0.5, -0.5])?;
+/// rec.finalize()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct WavRecorder {
+    writer: BufWriter<File>,
+    channels: u16,
+    samples_written: u64,
+    samples_since_update: u64,
+    update_interval: u64,
+}
+
+impl WavRecorder {
+    /// Creates (or truncates) `path` and writes a placeholder WAV header.
+    ///
+    /// # Arguments
+    /// * `path` - Output file path
+    /// * `sample_rate` - Sample rate of the mono samples that will be written
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self> {
+        Self::with_channels(path, sample_rate, 1)
+    }
+
+    /// Like [`create`](Self::create), for `channels` interleaved channels
+    /// (e.g. 2 for the left/right pairs captured in stereo mode).
+    ///
+    /// # Panics
+    /// Panics if `channels` is zero.
+    pub fn with_channels<P: AsRef<Path>>(path: P, sample_rate: u32, channels: u16) -> Result<Self> {
+        assert!(channels > 0, "at least one channel is required");
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&wav_header(sample_rate, channels, 0))?;
+        Ok(Self {
+            writer,
+            channels,
+            samples_written: 0,
+            samples_since_update: 0,
+            update_interval: u64::from(sample_rate * HEADER_UPDATE_SECS) * u64::from(channels),
+        })
+    }
+
+    /// Appends f32 samples (range -1.0 to 1.0), interleaved if the file has
+    /// more than one channel, converted to 16-bit PCM.
+    ///
+    /// Fails once the file reaches the WAV size limit; the samples that
+    /// still fit are written and the header is brought up to date first.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        // Stop on a whole frame so the channels stay aligned
+        let channels = u64::from(self.channels);
+        let room = MAX_DATA_LEN / 2 / channels * channels - self.samples_written;
+        let (fits, rest) = samples.split_at(samples.len().min(room as usize));
+        for &s in fits {
+            let pcm = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&pcm.to_le_bytes())?;
+        }
+        let n = fits.len() as u64;
+        self.samples_written += n;
+        self.samples_since_update += n;
+
+        if !rest.is_empty() {
+            self.update_header()?;
+            return Err(Error::other("reached the 4 GiB WAV size limit"));
+        }
+
+        if self.samples_since_update >= self.update_interval {
+            self.update_header()?;
+            self.samples_since_update = 0;
+        }
+        Ok(())
+    }
+
+    /// Number of samples written so far, counting every channel.
+    pub fn samples_written(&self) -> u64 {
+        self.samples_written
+    }
+
+    /// Number of frames (one sample per channel) written so far.
+    pub fn frames_written(&self) -> u64 {
+        self.samples_written / u64::from(self.channels)
+    }
+
+    /// Flushes buffered samples and rewrites the header length fields.
+    ///
+    /// Must be called on shutdown for the final header to match the data.
+    pub fn finalize(mut self) -> Result<()> {
+        self.update_header()
+    }
+
+    fn update_header(&mut self) -> Result<()> {
+        // write_samples never goes past MAX_DATA_LEN, so both sizes fit a u32
+        let data_len = self.samples_written * 2;
+        let riff_len = u64::from(HEADER_LEN) - 8 + data_len;
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&(riff_len as u32).to_le_bytes())?;
+        file.seek(SeekFrom::Start(40))?;
+        file.write_all(&(data_len as u32).to_le_bytes())?;
+        file.seek(SeekFrom::End(0))?;
+        file.flush()
+    }
+}
+
+/// Builds a 44-byte 16-bit PCM WAV header for `data_len` bytes of data.
+fn wav_header(sample_rate: u32, channels: u16, data_len: u32) -> [u8; HEADER_LEN as usize] {
+    let block_align = channels * 2;
+    let mut buf = [0u8; HEADER_LEN as usize];
+    buf[0..4].copy_from_slice(b"RIFF");
+    buf[4..8].copy_from_slice(&(HEADER_LEN - 8 + data_len).to_le_bytes());
+    buf[8..12].copy_from_slice(b"WAVE");
+    buf[12..16].copy_from_slice(b"fmt ");
+    buf[16..20].copy_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    buf[20..22].copy_from_slice(&1u16.to_le_bytes()); // PCM
+    buf[22..24].copy_from_slice(&channels.to_le_bytes());
+    buf[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    buf[28..32].copy_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes()); // byte rate
+    buf[32..34].copy_from_slice(&block_align.to_le_bytes());
+    buf[34..36].copy_from_slice(&16u16.to_le_bytes()); // bits per sample
+    buf[36..40].copy_from_slice(b"data");
+    buf[40..44].copy_from_slice(&data_len.to_le_bytes());
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_finalized_header_matches_sample_count() {
+        let path = std::env::temp_dir().join(format!("wled-rec-{}.wav", std::process::id()));
+        let mut rec = WavRecorder::create(&path, 48000).unwrap();
+        rec.write_samples(&vec![0.25; 1000]).unwrap();
+        rec.write_samples(&vec![-0.25; 234]).unwrap();
+        assert_eq!(rec.samples_written(), 1234);
+        rec.finalize().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..12], b"WAVE");
+        assert_eq!(read_u32(&bytes, 40), 1234 * 2, "data chunk size");
+        assert_eq!(read_u32(&bytes, 4) as usize, bytes.len() - 8, "RIFF size");
+        assert_eq!(bytes.len(), 44 + 1234 * 2);
+    }
+
+    #[test]
+    fn test_header_updated_periodically() {
+        let path = std::env::temp_dir().join(format!("wled-rec-p-{}.wav", std::process::id()));
+        let mut rec = WavRecorder::create(&path, 8000).unwrap();
+        // More than one second of audio triggers an in-flight header update
+        rec.write_samples(&vec![0.1; 8000]).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(read_u32(&bytes, 40), 8000 * 2);
+
+        rec.finalize().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stereo_header_and_frame_count() {
+        let path = std::env::temp_dir().join(format!("wled-rec-s-{}.wav", std::process::id()));
+        let mut rec = WavRecorder::with_channels(&path, 48000, 2).unwrap();
+        rec.write_samples(&[0.5, -0.5].repeat(480)).unwrap();
+        assert_eq!(rec.samples_written(), 960);
+        assert_eq!(rec.frames_written(), 480);
+        rec.finalize().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[22], bytes[23]]), 2, "channels");
+        assert_eq!(read_u32(&bytes, 28), 48000 * 4, "byte rate");
+        assert_eq!(u16::from_le_bytes([bytes[32], bytes[33]]), 4, "block align");
+        assert_eq!(read_u32(&bytes, 40), 960 * 2);
+    }
+
+    #[test]
+    fn test_recording_stops_at_wav_size_limit() {
+        let path = std::env::temp_dir().join(format!("wled-rec-l-{}.wav", std::process::id()));
+        let mut rec = WavRecorder::create(&path, 48000).unwrap();
+        // Pretend ~12.4 hours are already on disk, 10 samples short of full
+        rec.samples_written = u64::from(u32::MAX / 2) - 28;
+        assert_eq!(MAX_DATA_LEN / 2 - rec.samples_written, 10);
+
+        let err = rec.write_samples(&[0.5; 100]).unwrap_err();
+        assert!(err.to_string().contains("4 GiB"), "{err}");
+        assert_eq!(rec.samples_written(), MAX_DATA_LEN / 2);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 10 * 2);
+        assert_eq!(u64::from(read_u32(&bytes, 40)), MAX_DATA_LEN / 2 * 2);
+        assert_eq!(read_u32(&bytes, 4), u32::MAX - 1, "RIFF size");
+    }
+}
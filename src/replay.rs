@@ -0,0 +1,90 @@
+use crate::dsp::DspProcessor;
+use crate::packet::AudioSyncPacketV2;
+
+/// Size in bytes of one serialized V2 packet.
+pub const PACKET_LEN: usize = 44;
+
+/// Runs `samples` through a fresh default `DspProcessor` and serializes every
+/// frame as a V2 packet with the frame counter zeroed.
+///
+/// Used to produce and check golden packet files for DSP regression tests.
+pub fn render_packets(samples: &[f32], sample_rate: u32) -> Vec<[u8; PACKET_LEN]> {
+    let mut dsp = DspProcessor::new(sample_rate);
+    dsp.push_samples(samples)
+        .iter()
+        .map(|frame| AudioSyncPacketV2::from(frame).to_bytes(0))
+        .collect()
+}
+
+/// Splits a golden file (concatenated 44-byte packets) into packets.
+pub fn parse_golden(bytes: &[u8]) -> Result<Vec<[u8; PACKET_LEN]>, String> {
+    if !bytes.len().is_multiple_of(PACKET_LEN) {
+        return Err(format!(
+            "golden file length {} is not a multiple of {PACKET_LEN}",
+            bytes.len()
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(PACKET_LEN)
+        .map(|c| c.try_into().unwrap())
+        .collect())
+}
+
+/// Compares rendered packets against golden packets.
+///
+/// Integer fields must match exactly except FFT bins, which may differ by 1.
+/// Float fields are compared with a small tolerance so that FFT rounding
+/// differences between CPUs (e.g. SIMD paths) don't cause false failures.
+///
+/// # Returns
+/// `Err` describing the first mismatching packet and field.
+pub fn compare_packets(
+    actual: &[[u8; PACKET_LEN]],
+    golden: &[[u8; PACKET_LEN]],
+) -> Result<(), String> {
+    if actual.len() != golden.len() {
+        return Err(format!(
+            "packet count mismatch: got {}, golden has {}",
+            actual.len(),
+            golden.len()
+        ));
+    }
+
+    for (i, (a, g)) in actual.iter().zip(golden).enumerate() {
+        let f32_at = |buf: &[u8; PACKET_LEN], off: usize| {
+            f32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+        };
+        let float_fields = [
+            ("sampleRaw", 8, 0.05),
+            ("sampleSmth", 12, 0.05),
+            ("FFT_Magnitude", 36, 1e-3),
+            ("FFT_MajorPeak", 40, 1e-4),
+        ];
+
+        if a[..8] != g[..8] {
+            return Err(format!("packet {i}: header differs"));
+        }
+        for (name, off, rel_tol) in float_fields {
+            let (x, y) = (f32_at(a, off), f32_at(g, off));
+            if (x - y).abs() > rel_tol * y.abs().max(1.0) {
+                return Err(format!("packet {i}: {name} {x} != golden {y}"));
+            }
+        }
+        if a[16] != g[16] {
+            return Err(format!(
+                "packet {i}: samplePeak {} != golden {}",
+                a[16], g[16]
+            ));
+        }
+        for bin in 0..16 {
+            let (x, y) = (a[18 + bin], g[18 + bin]);
+            if x.abs_diff(y) > 1 {
+                return Err(format!("packet {i}: fftResult[{bin}] {x} != golden {y}"));
+            }
+        }
+        if a[34..36] != g[34..36] {
+            return Err(format!("packet {i}: zeroCrossingCount differs"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Streaming sample-rate conversion for the capture path.
+//!
+//! Devices don't always open at the same rate (44.1 kHz one run, 48 kHz the
+//! next), which shifts the frequency each FFT bin covers. A [`Resampler`]
+//! converts the captured stream to a fixed rate so the DSP always sees the
+//! same one. Conversion is linear interpolation between neighbouring input
+//! frames, tracked with exact integer positions so non-integer ratios such
+//! as 48000 -> 44100 don't drift over long runs. Each output frame waits for
+//! the input frame after it, so conversion adds one input frame of latency
+//! (about 21µs at 48 kHz) on top of the capture buffering.
+//!
+//! When downsampling, the input first goes through a linear-phase FIR
+//! low-pass just below the new Nyquist frequency, so content the new rate
+//! can't represent is removed instead of folding back into the bins. The
+//! filter delays the signal by a further 31 input frames (about 0.65 ms at
+//! 48 kHz).
+
+/// Length of the anti-aliasing filter, odd so its delay is whole frames
+const TAPS: usize = 63;
+
+/// Anti-aliasing cutoff as a fraction of the output rate (Nyquist is 0.5)
+const CUTOFF: f64 = 0.45;
+
+/// Blackman-windowed sinc low-pass over interleaved frames.
+struct LowPass {
+    taps: Vec<f32>,
+    /// The last `TAPS - 1` input frames (silence before the first chunk)
+    history: Vec<f32>,
+}
+
+impl LowPass {
+    /// `cutoff` is in cycles per input sample.
+    fn new(cutoff: f64, channels: usize) -> Self {
+        use std::f64::consts::{PI, TAU};
+        let mid = (TAPS - 1) as f64 / 2.0;
+        let taps: Vec<f64> = (0..TAPS)
+            .map(|i| {
+                let x = i as f64 - mid;
+                let sinc = if x == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (TAU * cutoff * x).sin() / (PI * x)
+                };
+                let phase = TAU * i as f64 / (TAPS - 1) as f64;
+                sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+            })
+            .collect();
+        // Unity gain at DC
+        let sum: f64 = taps.iter().sum();
+        Self {
+            taps: taps.iter().map(|t| (t / sum) as f32).collect(),
+            history: vec![0.0; (TAPS - 1) * channels],
+        }
+    }
+
+    fn process(&mut self, input: &[f32], channels: usize) -> Vec<f32> {
+        self.history.extend_from_slice(input);
+        let out = (0..input.len())
+            .map(|i| {
+                let (frame, ch) = (i / channels, i % channels);
+                self.taps
+                    .iter()
+                    .enumerate()
+                    .map(|(j, t)| t * self.history[(frame + j) * channels + ch])
+                    .sum()
+            })
+            .collect();
+        self.history.drain(..input.len());
+        out
+    }
+}
+
+/// Converts interleaved audio from one sample rate to another, chunk by chunk.
+pub struct Resampler {
+    from: u64,
+    to: u64,
+    channels: usize,
+    /// Position of the next output frame in units of `1 / to` input frames,
+    /// where 0 is `prev` and `to` is the first frame of the next chunk
+    pos: u64,
+    /// Last input frame of the previous chunk (silence before the first)
+    prev: Vec<f32>,
+    /// Anti-aliasing filter, only when downsampling
+    filter: Option<LowPass>,
+}
+
+impl Resampler {
+    /// Creates a resampler from `from` Hz to `to` Hz for `channels`
+    /// interleaved channels.
+    ///
+    /// # Panics
+    /// Panics if either rate or `channels` is zero.
+    pub fn new(from: u32, to: u32, channels: usize) -> Self {
+        assert!(from > 0 && to > 0, "sample rates must be positive");
+        assert!(channels > 0, "at least one channel is required");
+        Self {
+            from: from.into(),
+            to: to.into(),
+            channels,
+            pos: 0,
+            prev: vec![0.0; channels],
+            filter: (to < from)
+                .then(|| LowPass::new(CUTOFF * f64::from(to) / f64::from(from), channels)),
+        }
+    }
+
+    /// Resamples one chunk of interleaved input.
+    ///
+    /// State carries over between calls, so a stream split into chunks of
+    /// any size converts to the same output as the whole stream at once.
+    /// Returns `None` if `input` isn't a whole number of frames.
+    pub fn process(&mut self, input: &[f32]) -> Option<Vec<f32>> {
+        let ch = self.channels;
+        if !input.len().is_multiple_of(ch) {
+            return None;
+        }
+        let filtered;
+        let input = match &mut self.filter {
+            Some(filter) => {
+                filtered = filter.process(input, ch);
+                &filtered[..]
+            }
+            None => input,
+        };
+        let frames = (input.len() / ch) as u64;
+        // Frame k of the chunk's timeline: 0 is `prev`, 1.. are the input
+        let frame = |k: u64| match k {
+            0 => &self.prev[..],
+            k => &input[(k as usize - 1) * ch..k as usize * ch],
+        };
+
+        let mut out = Vec::with_capacity((frames * self.to / self.from) as usize * ch + ch);
+        // Each output frame interpolates between frames k and k + 1
+        while self.pos < frames * self.to {
+            let k = self.pos / self.to;
+            let t = (self.pos % self.to) as f32 / self.to as f32;
+            let (a, b) = (frame(k), frame(k + 1));
+            out.extend(a.iter().zip(b).map(|(a, b)| a + (b - a) * t));
+            self.pos += self.from;
+        }
+        if frames > 0 {
+            self.pos -= frames * self.to;
+            self.prev.copy_from_slice(&input[input.len() - ch..]);
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (std::f32::consts::TAU * freq * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_resampler_converts_non_integer_ratios() {
+        // One second at 48 kHz becomes one second at 44.1 kHz, in odd chunks
+        let input = sine(440.0, 48000, 48000);
+        let mut resampler = Resampler::new(48000, 44100, 1);
+        let chunked: Vec<f32> = input
+            .chunks(317)
+            .flat_map(|chunk| resampler.process(chunk).unwrap())
+            .collect();
+        assert_eq!(chunked.len(), 44100);
+
+        let whole = Resampler::new(48000, 44100, 1).process(&input).unwrap();
+        assert_eq!(chunked, whole);
+
+        // Apart from the one-frame and filter delays, the output is the same
+        // tone at the new rate
+        let delay = (1 + TAPS / 2) as f32 / 48000.0;
+        for (i, &y) in chunked.iter().enumerate().skip(TAPS) {
+            let t = i as f32 / 44100.0 - delay;
+            let expected = (std::f32::consts::TAU * 440.0 * t).sin();
+            assert!((y - expected).abs() < 0.01, "frame {i}: {y} vs {expected}");
+        }
+    }
+
+    #[test]
+    fn test_downsampling_filters_content_above_new_nyquist() {
+        // At 16 kHz a 12 kHz tone would alias to 4 kHz; a 3 kHz tone passes
+        let peak = |freq: f32| {
+            let out = Resampler::new(48000, 16000, 1)
+                .process(&sine(freq, 48000, 48000))
+                .unwrap();
+            out[TAPS..].iter().fold(0.0f32, |m, y| m.max(y.abs()))
+        };
+        assert!(peak(12000.0) < 0.01);
+        assert!((peak(3000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_resampler_keeps_channels_interleaved() {
+        // Left is constant, right is silent; upsample 2x
+        let input = [1.0, 0.0, 1.0, 0.0, 1.0, 0.0];
+        let mut resampler = Resampler::new(22050, 44100, 2);
+        let out = resampler.process(&input).unwrap();
+        assert_eq!(out.len(), 12);
+        // After ramping up from the initial silence, left holds at 1.0
+        assert_eq!(&out[..4], [0.0, 0.0, 0.5, 0.0]);
+        assert!(out[4..].chunks(2).all(|f| f == [1.0, 0.0]));
+        assert_eq!(resampler.process(&input[..3]), None);
+
+        let same = Resampler::new(48000, 48000, 1)
+            .process(&[0.25, 0.5])
+            .unwrap();
+        assert_eq!(same, [0.0, 0.25]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Quiet-hours scheduling for the packet output.
+//!
+//! A [`QuietHours`] schedule holds daily time ranges during which the main
+//! loop stops streaming (or streams zeros), so installations go dark at night
+//! without stopping the service. Ranges are matched against local wall-clock
+//! time at minute resolution.
+
+use chrono::{Local, Timelike};
+use std::fmt;
+use std::str::FromStr;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A daily `HH:MM-HH:MM` range, start inclusive and end exclusive.
+///
+/// A range whose end is before its start wraps past midnight
+/// (`23:00-07:00` covers the night). Equal start and end is the whole day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    start: u16, // Minutes since midnight
+    end: u16,
+}
+
+impl TimeRange {
+    /// Returns whether `minute` (minutes since midnight) falls in the range.
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+fn parse_clock(s: &str) -> Result<u16, String> {
+    let (h, m) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("expected HH:MM, got '{s}'"))?;
+    let h: u16 = h.parse().map_err(|_| format!("invalid hour in '{s}'"))?;
+    let m: u16 = m.parse().map_err(|_| format!("invalid minute in '{s}'"))?;
+    if h > 23 || m > 59 {
+        return Err(format!("'{s}' is not a valid time of day"));
+    }
+    Ok(h * 60 + m)
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    /// Parses `HH:MM-HH:MM`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got '{s}'"))?;
+        Ok(Self {
+            start: parse_clock(start)?,
+            end: parse_clock(end)?,
+        })
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// Set of daily quiet windows.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuietHours {
+    ranges: Vec<TimeRange>,
+}
+
+impl QuietHours {
+    pub fn new(ranges: Vec<TimeRange>) -> Self {
+        Self { ranges }
+    }
+
+    /// Returns whether `minute` (minutes since midnight) is in any range.
+    pub fn is_quiet_at(&self, minute: u16) -> bool {
+        let minute = minute % MINUTES_PER_DAY;
+        self.ranges.iter().any(|r| r.contains(minute))
+    }
+
+    /// Returns whether the current local time is in any range.
+    pub fn is_quiet_now(&self) -> bool {
+        if self.ranges.is_empty() {
+            return false;
+        }
+        let now = Local::now();
+        self.is_quiet_at((now.hour() * 60 + now.minute()) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(clock: &str) -> u16 {
+        parse_clock(clock).unwrap()
+    }
+
+    #[test]
+    fn test_parse_time_range() {
+        let r: TimeRange = "23:00-07:30".parse().unwrap();
+        assert_eq!(r.to_string(), "23:00-07:30");
+        assert!("23:00".parse::<TimeRange>().is_err());
+        assert!("24:00-07:00".parse::<TimeRange>().is_err());
+        assert!("22:60-07:00".parse::<TimeRange>().is_err());
+        assert!("ab:00-07:00".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_quiet_hours_same_day_range() {
+        let quiet = QuietHours::new(vec!["13:00-14:30".parse().unwrap()]);
+        assert!(quiet.is_quiet_at(at("13:00")));
+        assert!(quiet.is_quiet_at(at("14:29")));
+        assert!(!quiet.is_quiet_at(at("14:30")));
+        assert!(!quiet.is_quiet_at(at("12:59")));
+        assert!(!quiet.is_quiet_at(at("02:00")));
+    }
+
+    #[test]
+    fn test_quiet_hours_wrap_past_midnight() {
+        let quiet = QuietHours::new(vec!["23:00-07:00".parse().unwrap()]);
+        assert!(quiet.is_quiet_at(at("23:00")));
+        assert!(quiet.is_quiet_at(at("23:59")));
+        assert!(quiet.is_quiet_at(at("00:00")));
+        assert!(quiet.is_quiet_at(at("06:59")));
+        assert!(!quiet.is_quiet_at(at("07:00")));
+        assert!(!quiet.is_quiet_at(at("12:00")));
+        assert!(!quiet.is_quiet_at(at("22:59")));
+    }
+
+    #[test]
+    fn test_quiet_hours_multiple_ranges_and_empty() {
+        let quiet = QuietHours::new(vec![
+            "23:00-07:00".parse().unwrap(),
+            "12:00-13:00".parse().unwrap(),
+        ]);
+        assert!(quiet.is_quiet_at(at("12:30")));
+        assert!(quiet.is_quiet_at(at("03:00")));
+        assert!(!quiet.is_quiet_at(at("18:00")));
+
+        let whole_day = QuietHours::new(vec!["08:00-08:00".parse().unwrap()]);
+        assert!(whole_day.is_quiet_at(at("08:00")));
+        assert!(whole_day.is_quiet_at(at("07:59")));
+
+        assert!(!QuietHours::default().is_quiet_at(at("03:00")));
+        assert!(!QuietHours::default().is_quiet_now());
+    }
+}
@@ -0,0 +1,260 @@
+//! Where the main loop's audio comes from.
+//!
+//! An [`AudioSource`] yields chunks of f32 samples at a fixed rate, either
+//! from a live capture stream ([`CaptureSource`]) or from a WAV file
+//! ([`WavSource`]). Both feed the same DSP and packet pipeline, so a file
+//! plays exactly like live audio, which makes runs reproducible.
+
+use crate::wav::WavAudio;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// A stream of sample chunks at a fixed sample rate.
+pub trait AudioSource {
+    /// Sample rate of every chunk, in Hz.
+    fn sample_rate(&self) -> u32;
+
+    /// Waits up to `timeout` for the next chunk.
+    ///
+    /// `Err(Timeout)` means no chunk is ready yet; `Err(Disconnected)` means
+    /// the source has ended and will never yield another.
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Vec<f32>, RecvTimeoutError>;
+}
+
+/// Live audio from [`open_capture_stream`](crate::audio::open_capture_stream).
+///
+/// The stream itself stays with the caller, which must keep it alive.
+pub struct CaptureSource {
+    rx: Receiver<Vec<f32>>,
+    sample_rate: u32,
+}
+
+impl CaptureSource {
+    pub fn new(rx: Receiver<Vec<f32>>, sample_rate: u32) -> Self {
+        Self { rx, sample_rate }
+    }
+}
+
+impl AudioSource for CaptureSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Vec<f32>, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+}
+
+/// Decodes a WAV file with `hound` and downmixes it to mono.
+///
+/// Unlike [`read_wav`](crate::wav::read_wav), any PCM bit depth hound reads
+/// (8, 16, 24 or 32 bits) and 32-bit float files are accepted, which covers
+/// typical offline renders and test material.
+pub fn decode_wav<P: AsRef<Path>>(path: P) -> std::io::Result<WavAudio> {
+    let reader = hound::WavReader::open(path).map_err(hound_error)?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(hound_error)?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()
+                .map_err(hound_error)?
+        }
+    };
+    let channels = spec.channels as usize;
+    Ok(WavAudio {
+        sample_rate: spec.sample_rate,
+        samples: samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect(),
+    })
+}
+
+fn hound_error(e: hound::Error) -> Error {
+    match e {
+        hound::Error::IoError(e) => e,
+        e => Error::new(ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+/// Length of each [`WavSource`] chunk, about what a driver callback delivers.
+const WAV_CHUNK: Duration = Duration::from_millis(10);
+
+/// Decoded WAV audio played back in fixed-size chunks.
+///
+/// In real time (the default), each chunk is held back until the moment it
+/// would have been captured live, so packets go out at the same pace as with
+/// a device; otherwise chunks are yielded as fast as they're asked for.
+pub struct WavSource {
+    audio: WavAudio,
+    chunk_len: usize,
+    pos: usize,
+    realtime: bool,
+    start: Option<Instant>, // When the first chunk was played
+}
+
+impl WavSource {
+    pub fn new(audio: WavAudio, realtime: bool) -> Self {
+        let chunk_len = ((audio.sample_rate as f64 * WAV_CHUNK.as_secs_f64()) as usize).max(1);
+        Self {
+            audio,
+            chunk_len,
+            pos: 0,
+            realtime,
+            start: None,
+        }
+    }
+
+    /// Seconds of audio in the file.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.audio.samples.len() as f64 / self.audio.sample_rate as f64)
+    }
+}
+
+impl AudioSource for WavSource {
+    fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Vec<f32>, RecvTimeoutError> {
+        if self.pos >= self.audio.samples.len() {
+            return Err(RecvTimeoutError::Disconnected);
+        }
+        if self.realtime {
+            let start = *self.start.get_or_insert_with(Instant::now);
+            // A chunk is due once the audio before it would have played
+            let due =
+                start + Duration::from_secs_f64(self.pos as f64 / self.audio.sample_rate as f64);
+            let wait = due.saturating_duration_since(Instant::now());
+            if wait > timeout {
+                std::thread::sleep(timeout);
+                return Err(RecvTimeoutError::Timeout);
+            }
+            std::thread::sleep(wait);
+        }
+        let end = (self.pos + self.chunk_len).min(self.audio.samples.len());
+        let chunk = self.audio.samples[self.pos..end].to_vec();
+        self.pos = end;
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::DspProcessor;
+
+    fn tone(sample_rate: u32, secs: f32) -> WavAudio {
+        let len = (sample_rate as f32 * secs) as usize;
+        WavAudio {
+            sample_rate,
+            samples: (0..len).map(|i| (i as f32 * 0.05).sin() * 0.5).collect(),
+        }
+    }
+
+    fn drain(source: &mut dyn AudioSource) -> Vec<Vec<f32>> {
+        let mut chunks = Vec::new();
+        loop {
+            match source.recv_timeout(Duration::from_secs(1)) {
+                Ok(chunk) => chunks.push(chunk),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return chunks,
+            }
+        }
+    }
+
+    #[test]
+    fn test_wav_source_matches_whole_file_analysis() {
+        let audio = tone(48000, 1.0);
+        let samples = audio.samples.clone();
+        let mut source = WavSource::new(audio, false);
+        assert_eq!(source.sample_rate(), 48000);
+
+        let chunks = drain(&mut source);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 480));
+        assert_eq!(chunks.concat(), samples);
+
+        // Chunked playback drives the DSP exactly like the whole buffer
+        let mut chunked = DspProcessor::new(48000);
+        let frames: Vec<_> = chunks
+            .iter()
+            .flat_map(|chunk| chunked.push_samples(chunk))
+            .collect();
+        let whole = DspProcessor::new(48000).push_samples(&samples);
+        assert_eq!(frames.len(), whole.len());
+        for (a, b) in frames.iter().zip(&whole) {
+            assert_eq!(a.fft_result, b.fft_result);
+        }
+    }
+
+    #[test]
+    fn test_decode_wav_reads_float_and_24_bit() {
+        let dir = std::env::temp_dir();
+        let write = |name: &str, spec: hound::WavSpec, frames: &[[f32; 2]]| {
+            let path = dir.join(format!("wled-src-{name}-{}.wav", std::process::id()));
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            for &frame in frames {
+                for s in frame {
+                    match spec.sample_format {
+                        hound::SampleFormat::Float => writer.write_sample(s).unwrap(),
+                        hound::SampleFormat::Int => {
+                            let max = (1i32 << (spec.bits_per_sample - 1)) as f32;
+                            writer.write_sample((s * max) as i32).unwrap()
+                        }
+                    }
+                }
+            }
+            writer.finalize().unwrap();
+            path
+        };
+        let frames = [[0.5, 0.0], [-0.25, -0.25], [0.0, 1.0 - 1e-6]];
+        for (sample_format, bits) in [
+            (hound::SampleFormat::Float, 32),
+            (hound::SampleFormat::Int, 24),
+            (hound::SampleFormat::Int, 16),
+        ] {
+            let spec = hound::WavSpec {
+                channels: 2,
+                sample_rate: 44100,
+                bits_per_sample: bits,
+                sample_format,
+            };
+            let path = write(&format!("{bits}"), spec, &frames);
+            let audio = decode_wav(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(audio.sample_rate, 44100);
+            let expected = [0.25, -0.25, 0.5];
+            assert_eq!(audio.samples.len(), 3);
+            for (got, want) in audio.samples.iter().zip(expected) {
+                assert!((got - want).abs() < 1e-4, "{bits}-bit: {got} vs {want}");
+            }
+        }
+
+        let err = decode_wav(dir.join("wled-src-missing.wav")).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_wav_source_paces_in_real_time() {
+        let mut source = WavSource::new(tone(8000, 0.1), true);
+        assert_eq!(source.duration(), Duration::from_millis(100));
+
+        let start = Instant::now();
+        // The first chunk is due immediately, the rest only as time passes
+        assert!(source.recv_timeout(Duration::ZERO).is_ok());
+        let chunks = drain(&mut source);
+        assert_eq!(chunks.len(), 9);
+        // The last chunk is due 90ms in
+        assert!(start.elapsed() >= Duration::from_millis(85));
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// Decoded WAV audio, downmixed to mono.
+pub struct WavAudio {
+    pub sample_rate: u32,
+    /// Mono f32 samples (range -1.0 to 1.0)
+    pub samples: Vec<f32>,
+}
+
+/// Reads a 16-bit PCM WAV file and downmixes it to mono.
+///
+/// Supports the files written by [`WavRecorder`](crate::recorder::WavRecorder)
+/// and any other canonical 16-bit PCM WAV; unknown chunks are skipped.
+///
+/// # Returns
+/// * `Ok(WavAudio)` - Sample rate and mono samples
+/// * `Err(io::Error)` - If the file can't be read or isn't 16-bit PCM WAV
+pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<WavAudio> {
+    parse_wav(&std::fs::read(path)?)
+}
+
+fn parse_wav(bytes: &[u8]) -> Result<WavAudio> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE file"));
+    }
+
+    let mut format: Option<(u16, u32)> = None; // (channels, sample_rate)
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body = &bytes[pos + 8..(pos + 8 + len).min(bytes.len())];
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(invalid("truncated fmt chunk"));
+                }
+                let audio_format = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                if audio_format != 1 || bits != 16 || channels == 0 {
+                    return Err(invalid("only 16-bit PCM WAV is supported"));
+                }
+                format = Some((channels, sample_rate));
+            }
+            b"data" => {
+                let (channels, sample_rate) = format.ok_or_else(|| invalid("data before fmt"))?;
+                let samples = body
+                    .chunks_exact(2 * channels as usize)
+                    .map(|frame| {
+                        let sum: f32 = frame
+                            .chunks_exact(2)
+                            .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / i16::MAX as f32)
+                            .sum();
+                        sum / channels as f32
+                    })
+                    .collect();
+                return Ok(WavAudio {
+                    sample_rate,
+                    samples,
+                });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        pos += 8 + len + (len & 1);
+    }
+
+    Err(invalid("no data chunk"))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::WavRecorder;
+
+    #[test]
+    fn test_recorder_round_trip() {
+        let path = std::env::temp_dir().join(format!("wled-wav-{}.wav", std::process::id()));
+        let mut rec = WavRecorder::create(&path, 44100).unwrap();
+        rec.write_samples(&[0.0, 0.5, -0.5, 1.0]).unwrap();
+        rec.finalize().unwrap();
+
+        let audio = read_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(audio.sample_rate, 44100);
+        assert_eq!(audio.samples.len(), 4);
+        for (got, want) in audio.samples.iter().zip([0.0, 0.5, -0.5, 1.0]) {
+            assert!((got - want).abs() < 1e-4, "{got} vs {want}");
+        }
+    }
+
+    #[test]
+    fn test_rejects_non_wav() {
+        assert!(parse_wav(b"not a wav file at all").is_err());
+    }
+}
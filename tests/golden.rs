@@ -0,0 +1,34 @@
+//! DSP regression test: replays a fixed WAV and compares the resulting
+//! packets against a committed golden file.
+//!
+//! After an intentional DSP change, regenerate the golden file with:
+//! `cargo run -- --golden tests/fixtures/golden_input.wav tests/fixtures/golden_packets.bin`
+
+use wled_audio_server::replay::{compare_packets, parse_golden, render_packets};
+use wled_audio_server::wav::read_wav;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+#[test]
+fn test_replay_matches_golden_packets() {
+    let audio = read_wav(format!("{FIXTURES}/golden_input.wav")).unwrap();
+    let golden = std::fs::read(format!("{FIXTURES}/golden_packets.bin")).unwrap();
+    let golden = parse_golden(&golden).unwrap();
+
+    let actual = render_packets(&audio.samples, audio.sample_rate);
+    assert!(!actual.is_empty());
+    if let Err(e) = compare_packets(&actual, &golden) {
+        panic!("DSP output diverged from golden packets: {e}");
+    }
+}
+
+#[test]
+fn test_compare_detects_bin_changes() {
+    let audio = read_wav(format!("{FIXTURES}/golden_input.wav")).unwrap();
+    let actual = render_packets(&audio.samples, audio.sample_rate);
+
+    let mut tampered = actual.clone();
+    tampered[3][20] = tampered[3][20].wrapping_add(10);
+    assert!(compare_packets(&tampered, &actual).is_err());
+    assert!(compare_packets(&actual[1..], &actual).is_err());
+}